@@ -85,20 +85,17 @@ impl AssetLoader for GltfLoader {
             let mut transfer_ctx = renderer.start_transfer_operations();
             let vertices_handle = transfer_ctx.create_vertex_buffer(&vertices)?;
             let indices_handle = transfer_ctx.create_index_buffer(&indices)?;
-            transfer_ctx.submit_and_wait()?;
+            transfer_ctx.submit()?.await?;
 
             let mesh =
                 renderer.create_mesh(vertices_handle, indices_handle, sub_meshes.into_iter());
-            mesh.destroy_on_exit(&renderer);
 
             let blas = renderer.create_blas(std::iter::once(&mesh))?;
             debug!("Built BLASes !");
             let blas = blas.into_iter().next().unwrap();
-            blas.destroy_on_exit(&renderer);
 
             let tlas = renderer.create_tlas(std::iter::once(&blas))?;
             debug!("Build TLAS !");
-            tlas.destroy_on_exit(&renderer);
 
             let descriptor_set = renderer.create_descriptor_set_rt(&tlas)?;
 
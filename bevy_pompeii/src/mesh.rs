@@ -29,7 +29,7 @@ impl From<Handle<MeshAsset>> for MeshBundle {
     }
 }
 
-#[derive(Clone, TypeUuid)]
+#[derive(TypeUuid)]
 #[uuid = "c4ff691e-eaee-4369-84da-429838ea6e71"]
 pub struct MeshAsset {
     pub(crate) renderer: Weak<PompeiiRenderer>,
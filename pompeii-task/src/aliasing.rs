@@ -0,0 +1,376 @@
+use petgraph::{visit::EdgeRef, Direction};
+use vk_sync_fork::AccessType;
+
+use crate::{barriers::BarrierTracker, CompiledGraph, CompiledPass, QueueType, Res, TaskGraph};
+
+/// The range of passes, as indices in the schedule, during which a resource is in use.
+///
+/// It starts with the first pass transitioning the resource, which can be earlier than the
+/// first one using it inside a render group. The passes of the compute queue don't run in
+/// schedule order with the others, resources they use are in use for the whole schedule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceLifetime {
+    pub resource: Res,
+    pub first_use: usize,
+    pub last_use: usize,
+    /// What the last passes do with the resource, which the next one placed in its memory
+    /// must wait for
+    pub last_accesses: Vec<AccessType>,
+}
+
+impl ResourceLifetime {
+    pub fn overlaps(&self, other: &ResourceLifetime) -> bool {
+        self.first_use <= other.last_use && other.first_use <= self.last_use
+    }
+}
+
+/// What a resource needs from its memory, usually straight from `vkGet*MemoryRequirements`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryRequirements {
+    pub size: u64,
+    pub alignment: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AliasedResource {
+    pub resource: Res,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Hands memory over from a resource to the next one placed on top of it.
+///
+/// The previous content is lost, this is a memory barrier from the last accesses of
+/// `previous` to [AccessType::Nothing]. It must be recorded in the same pipeline barrier as the
+/// [barriers](CompiledPass::barriers) of the pass, so the first use of `next` waits for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AliasingBarrier {
+    /// Index of the pass to record the barrier before, the first one transitioning `next`
+    pub pass: usize,
+    pub previous: Res,
+    pub previous_accesses: Vec<AccessType>,
+    pub next: Res,
+}
+
+/// Placement of the transient resources inside one memory block.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AliasingPlan {
+    /// Size of the memory block to allocate
+    pub size: u64,
+    pub resources: Vec<AliasedResource>,
+    /// Sorted by pass
+    pub barriers: Vec<AliasingBarrier>,
+}
+
+impl<R, C> TaskGraph<R, C> {
+    /// A resource is transient when the host neither writes nor reads it, and it isn't imported
+    /// nor kept across frames.
+    pub fn is_transient(&self, Res(res): Res) -> bool {
+        !self.imports.contains_key(&res)
+            && !self.is_history(Res(res))
+            && !self
+                .graph
+                .edges_directed(res, Direction::Incoming)
+                .any(|edge| edge.source() == self.external_before_node)
+            && !self
+                .graph
+                .edges_directed(res, Direction::Outgoing)
+                .any(|edge| edge.target() == self.external_after_node)
+    }
+
+    /// Lifetimes of the transient resources over `passes`, in order of first use, along with
+    /// the accesses `tracker` saw last.
+    ///
+    /// Indices only order the passes of the same queue, a resource touched by the compute
+    /// queue is kept alive over the whole schedule so it never shares memory.
+    pub(crate) fn transient_lifetimes(
+        &self,
+        passes: &[CompiledPass],
+        tracker: &BarrierTracker,
+    ) -> Vec<ResourceLifetime> {
+        let mut lifetimes = Vec::<ResourceLifetime>::new();
+
+        for (i, pass) in passes.iter().enumerate() {
+            let used = pass
+                .reads
+                .iter()
+                .map(|read| read.resource)
+                .chain(pass.writes.iter().map(|write| write.resource))
+                .chain(pass.barriers.iter().map(|barrier| barrier.resource));
+
+            for resource in used.filter(|&res| self.is_transient(res)) {
+                match lifetimes.iter_mut().find(|l| l.resource == resource) {
+                    Some(lifetime) => lifetime.last_use = i,
+                    None => lifetimes.push(ResourceLifetime {
+                        resource,
+                        first_use: i,
+                        last_use: i,
+                        last_accesses: Vec::new(),
+                    }),
+                }
+            }
+        }
+
+        for lifetime in &mut lifetimes {
+            lifetime.last_accesses = tracker.last_accesses(lifetime.resource);
+
+            let on_compute = passes
+                .iter()
+                .filter(|pass| pass.queue == QueueType::Compute)
+                .any(|pass| {
+                    pass.reads
+                        .iter()
+                        .any(|read| read.resource == lifetime.resource)
+                        || pass
+                            .writes
+                            .iter()
+                            .any(|write| write.resource == lifetime.resource)
+                });
+            if on_compute {
+                lifetime.first_use = 0;
+                lifetime.last_use = passes.len() - 1;
+            }
+        }
+
+        lifetimes
+    }
+}
+
+impl CompiledGraph {
+    pub fn transient_lifetimes(&self) -> &[ResourceLifetime] {
+        &self.lifetimes
+    }
+
+    /// Place the transient resources in a single memory block, letting the ones that are
+    /// never alive at the same time share memory, along with the barriers between them.
+    ///
+    /// Every resource is assumed to be able to live in the same memory type.
+    pub fn aliasing_plan(&self, requirements: impl Fn(Res) -> MemoryRequirements) -> AliasingPlan {
+        plan_aliasing(&self.lifetimes, requirements)
+    }
+}
+
+/// Greedy placement, biggest resources first, each at the lowest offset that doesn't collide
+/// with an already placed resource alive at the same time.
+pub fn plan_aliasing(
+    lifetimes: &[ResourceLifetime],
+    requirements: impl Fn(Res) -> MemoryRequirements,
+) -> AliasingPlan {
+    let mut to_place = lifetimes
+        .iter()
+        .map(|lifetime| (lifetime, requirements(lifetime.resource)))
+        .collect::<Vec<_>>();
+    // Stable, so equal sizes stay in order of first use
+    to_place.sort_by_key(|(_, req)| std::cmp::Reverse(req.size));
+
+    let mut placed = Vec::<(&ResourceLifetime, AliasedResource)>::with_capacity(to_place.len());
+    for (lifetime, req) in to_place {
+        let mut taken = placed
+            .iter()
+            .filter(|(other, _)| other.overlaps(lifetime))
+            .map(|(_, alloc)| (alloc.offset, alloc.offset + alloc.size))
+            .collect::<Vec<_>>();
+        taken.sort_unstable();
+
+        let mut offset = 0;
+        for (start, end) in taken {
+            if offset + req.size <= start {
+                break;
+            }
+            offset = offset.max(align_up(end, req.alignment));
+        }
+
+        placed.push((
+            lifetime,
+            AliasedResource {
+                resource: lifetime.resource,
+                offset,
+                size: req.size,
+            },
+        ));
+    }
+
+    // Every resource ending before the next one starts in the same memory, some of them may
+    // already be waited on through the others but their accesses can be on unrelated stages
+    let mut barriers = Vec::new();
+    for (next, next_alloc) in &placed {
+        for (previous, previous_alloc) in &placed {
+            if previous.last_use < next.first_use
+                && previous_alloc.offset < next_alloc.offset + next_alloc.size
+                && next_alloc.offset < previous_alloc.offset + previous_alloc.size
+            {
+                barriers.push(AliasingBarrier {
+                    pass: next.first_use,
+                    previous: previous.resource,
+                    previous_accesses: previous.last_accesses.clone(),
+                    next: next.resource,
+                });
+            }
+        }
+    }
+    barriers.sort_by_key(|barrier| (barrier.pass, barrier.next, barrier.previous));
+
+    let mut resources = placed
+        .into_iter()
+        .map(|(_, alloc)| alloc)
+        .collect::<Vec<_>>();
+    resources.sort_by_key(|alloc| alloc.resource);
+
+    AliasingPlan {
+        size: resources
+            .iter()
+            .map(|alloc| alloc.offset + alloc.size)
+            .max()
+            .unwrap_or(0),
+        resources,
+        barriers,
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    if alignment <= 1 {
+        value
+    } else {
+        value.div_ceil(alignment) * alignment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vk_sync_fork::AccessType;
+
+    use crate::{
+        tests::{compile, Graph, IMAGE},
+        AliasingBarrier, MemoryRequirements,
+    };
+
+    #[test]
+    fn lifetimes_of_transient_resources() {
+        let mut graph = Graph::new();
+
+        let gbuffer = graph.register_resource_image("GBuffer", IMAGE);
+        let hdr = graph.register_resource_image("HDR", IMAGE);
+        let output = graph.register_resource_image("Output", IMAGE);
+
+        graph
+            .create_raster_pass("Geometry")
+            .add_color_attachment(gbuffer);
+        graph
+            .create_raster_pass("Lighting")
+            .add_sampled(gbuffer)
+            .add_color_attachment(hdr);
+        graph
+            .create_raster_pass("Tonemap")
+            .add_sampled(hdr)
+            .add_color_attachment(output);
+        graph.copy_to_host(output);
+
+        let compiled = compile(&graph);
+        let lifetimes = compiled.transient_lifetimes();
+
+        assert_eq!(lifetimes.len(), 2);
+        assert_eq!(lifetimes[0].resource, gbuffer);
+        assert_eq!((lifetimes[0].first_use, lifetimes[0].last_use), (0, 1));
+        assert_eq!(lifetimes[1].resource, hdr);
+        assert_eq!((lifetimes[1].first_use, lifetimes[1].last_use), (1, 2));
+    }
+
+    #[test]
+    fn disjoint_lifetimes_share_memory() {
+        let mut graph = Graph::new();
+
+        let a = graph.register_resource_image("A", IMAGE);
+        let b = graph.register_resource_image("B", IMAGE);
+        let c = graph.register_resource_image("C", IMAGE);
+        let out = graph.register_resource_buffer("Out", 1024);
+
+        graph.create_raster_pass("1").add_color_attachment(a);
+        graph
+            .create_raster_pass("2")
+            .add_sampled(a)
+            .add_color_attachment(b);
+        graph
+            .create_raster_pass("3")
+            .add_sampled(b)
+            .add_color_attachment(c);
+        graph
+            .create_compute_pass("4")
+            .add_input_storage_buffer(c)
+            .add_output_storage_buffer(out);
+        graph.copy_to_host(out);
+
+        let compiled = compile(&graph);
+        let plan = compiled.aliasing_plan(|_| MemoryRequirements {
+            size: 100,
+            alignment: 64,
+        });
+
+        let offset_of = |res| {
+            plan.resources
+                .iter()
+                .find(|alloc| alloc.resource == res)
+                .unwrap()
+                .offset
+        };
+
+        // A and C are never alive together
+        assert_eq!(offset_of(a), offset_of(c));
+        assert_eq!(offset_of(b), 128);
+        assert_eq!(plan.size, 228);
+
+        // C waits for the last reads of A before taking its memory
+        assert_eq!(
+            plan.barriers,
+            [AliasingBarrier {
+                pass: 2,
+                previous: a,
+                previous_accesses: vec![AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer],
+                next: c,
+            }]
+        );
+    }
+
+    #[test]
+    fn async_compute_resources_are_not_aliased() {
+        let mut graph = Graph::new();
+
+        let color = graph.register_resource_image("Color", IMAGE);
+        let output = graph.register_resource_image("Output", IMAGE);
+        let particles = graph.register_resource_buffer("Particles", 1024);
+        let culled = graph.register_resource_buffer("Culled", 1024);
+
+        graph
+            .create_raster_pass("Scene")
+            .add_color_attachment(color);
+        graph
+            .create_raster_pass("Post")
+            .add_sampled(color)
+            .add_color_attachment(output);
+        graph
+            .create_compute_pass("Simulate")
+            .add_output_storage_buffer(particles);
+        graph
+            .create_compute_pass("Cull")
+            .add_input_storage_buffer(particles)
+            .add_output_storage_buffer(culled);
+        graph.copy_to_host(output);
+        graph.copy_to_host(culled);
+
+        let compiled = compile(&graph);
+        let plan = compiled.aliasing_plan(|_| MemoryRequirements {
+            size: 100,
+            alignment: 1,
+        });
+
+        // The simulation may run while the scene is rendered, whatever the schedule says
+        let offset_of = |res| {
+            plan.resources
+                .iter()
+                .find(|alloc| alloc.resource == res)
+                .unwrap()
+                .offset
+        };
+        assert_ne!(offset_of(color), offset_of(particles));
+        assert!(plan.barriers.is_empty());
+    }
+}
@@ -0,0 +1,825 @@
+use std::collections::{HashMap, HashSet};
+
+use ash::vk;
+use vk_sync_fork::{AccessType, ImageLayout};
+
+use crate::{QueueTransfer, QueueType, ReadActionFlags, Res, SubresourceRange, WriteActionFlags};
+
+/// What kind of work a pass records, which decides the pipeline stages of its accesses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PassKind {
+    Upload,
+    Raster,
+    Compute,
+    RayTracing,
+    Transfer,
+    AccelerationStructureBuild,
+}
+
+/// A synchronization point for one resource, in the terms of `vk_sync_fork`.
+///
+/// The layouts are only meaningful for images.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Barrier {
+    pub resource: Res,
+    /// The mips and layers of an image the barrier applies to
+    pub range: SubresourceRange,
+    pub previous_accesses: Vec<AccessType>,
+    pub next_accesses: Vec<AccessType>,
+    pub previous_layout: ImageLayout,
+    pub next_layout: ImageLayout,
+    pub discard_contents: bool,
+    /// Set when the previous accesses happened on another queue
+    pub queue_transfer: Option<QueueTransfer>,
+}
+
+/// The state of an imported resource outside of the graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalState {
+    /// No access at all is the same as [AccessType::Nothing]
+    pub accesses: Vec<AccessType>,
+    /// Only meaningful for images
+    pub layout: ImageLayout,
+}
+
+impl ExternalState {
+    /// A single access in the optimal layout, [AccessType::Nothing] for undefined content.
+    pub fn new(access: AccessType) -> Self {
+        Self {
+            accesses: vec![access],
+            layout: ImageLayout::Optimal,
+        }
+    }
+
+    pub fn with_layout(mut self, layout: ImageLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+}
+
+/// The accesses a pass of kind `kind` does when reading with `flags`.
+pub fn read_accesses(kind: PassKind, flags: ReadActionFlags) -> Vec<AccessType> {
+    let mut accesses = Vec::new();
+
+    if flags.is_empty() {
+        // Plain bound buffer
+        accesses.extend_from_slice(match kind {
+            PassKind::Upload | PassKind::Transfer => &[AccessType::TransferRead][..],
+            PassKind::Raster => &[
+                AccessType::AnyShaderReadUniformBufferOrVertexBuffer,
+                AccessType::IndexBuffer,
+            ],
+            PassKind::Compute => &[AccessType::ComputeShaderReadUniformBuffer],
+            PassKind::RayTracing => &[AccessType::AnyShaderReadUniformBuffer],
+            // TODO: the geometry is really read as `SHADER_READ` in the build stage, but
+            //  vk-sync has no access type for it
+            PassKind::AccelerationStructureBuild => &[AccessType::AccelerationStructureBuildRead],
+        });
+    }
+    if flags.contains(ReadActionFlags::INPUT_ATTACHMENT) {
+        accesses.push(AccessType::FragmentShaderReadColorInputAttachment);
+    }
+    if flags.contains(ReadActionFlags::DEPTH_ATTACHMENT) {
+        accesses.push(AccessType::DepthStencilAttachmentRead);
+    }
+    if flags.contains(ReadActionFlags::SAMPLED) {
+        accesses.push(match kind {
+            PassKind::Compute => AccessType::ComputeShaderReadSampledImageOrUniformTexelBuffer,
+            PassKind::RayTracing => {
+                AccessType::RayTracingShaderReadSampledImageOrUniformTexelBuffer
+            }
+            _ => AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        });
+    }
+    if flags.contains(ReadActionFlags::TRANSFER) {
+        accesses.push(AccessType::TransferRead);
+    }
+    if flags.contains(ReadActionFlags::STORAGE) {
+        accesses.push(match kind {
+            PassKind::Compute => AccessType::ComputeShaderReadOther,
+            PassKind::RayTracing => AccessType::RayTracingShaderReadOther,
+            _ => AccessType::AnyShaderReadOther,
+        });
+    }
+    if flags.contains(ReadActionFlags::ACCELERATION_STRUCTURE) {
+        accesses.push(match kind {
+            PassKind::AccelerationStructureBuild => AccessType::AccelerationStructureBuildRead,
+            _ => AccessType::RayTracingShaderReadAccelerationStructure,
+        });
+    }
+
+    accesses
+}
+
+/// The accesses a pass of kind `kind` does when writing with `flags`.
+pub fn write_accesses(kind: PassKind, flags: WriteActionFlags) -> Vec<AccessType> {
+    let mut accesses = Vec::new();
+
+    if flags.contains(WriteActionFlags::COLOR_ATTACHMENT) {
+        accesses.push(AccessType::ColorAttachmentWrite);
+    }
+    if flags.contains(WriteActionFlags::DEPTH_ATTACHMENT) {
+        accesses.push(AccessType::DepthStencilAttachmentWrite);
+    }
+    if flags.contains(WriteActionFlags::TRANSFER) {
+        accesses.push(AccessType::TransferWrite);
+    }
+    if flags.contains(WriteActionFlags::STORAGE) {
+        accesses.push(match kind {
+            PassKind::Compute => AccessType::ComputeShaderWrite,
+            // Scratch buffer
+            PassKind::AccelerationStructureBuild => AccessType::AccelerationStructureBuildWrite,
+            _ => AccessType::AnyShaderWrite,
+        });
+    }
+    if flags.contains(WriteActionFlags::ACCELERATION_STRUCTURE) {
+        accesses.push(AccessType::AccelerationStructureBuildWrite);
+    }
+
+    accesses
+}
+
+/// Storage images can't live in an optimal layout.
+pub fn image_layout(read_flags: ReadActionFlags, write_flags: WriteActionFlags) -> ImageLayout {
+    if read_flags.contains(ReadActionFlags::STORAGE)
+        || write_flags.contains(WriteActionFlags::STORAGE)
+    {
+        ImageLayout::General
+    } else {
+        ImageLayout::Optimal
+    }
+}
+
+/// The layout `vk_sync_fork` picks for an image read in [ImageLayout::Optimal].
+fn optimal_read_layout(access: AccessType) -> vk::ImageLayout {
+    match access {
+        AccessType::TransferRead => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        AccessType::Present => vk::ImageLayout::PRESENT_SRC_KHR,
+        AccessType::ColorAttachmentRead => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        AccessType::DepthStencilAttachmentRead
+        | AccessType::FragmentShaderReadDepthStencilInputAttachment
+        | AccessType::RayTracingShaderReadDepthStencilInputAttachment => {
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        }
+        AccessType::VertexShaderReadSampledImageOrUniformTexelBuffer
+        | AccessType::TessellationControlShaderReadSampledImageOrUniformTexelBuffer
+        | AccessType::TessellationEvaluationShaderReadSampledImageOrUniformTexelBuffer
+        | AccessType::GeometryShaderReadSampledImageOrUniformTexelBuffer
+        | AccessType::FragmentShaderReadSampledImageOrUniformTexelBuffer
+        | AccessType::FragmentShaderReadColorInputAttachment
+        | AccessType::ComputeShaderReadSampledImageOrUniformTexelBuffer
+        | AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer
+        | AccessType::RayTracingShaderReadSampledImageOrUniformTexelBuffer
+        | AccessType::RayTracingShaderReadColorInputAttachment => {
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        }
+        _ => vk::ImageLayout::GENERAL,
+    }
+}
+
+fn is_write(access: AccessType) -> bool {
+    matches!(
+        access,
+        AccessType::CommandBufferWriteNVX
+            | AccessType::VertexShaderWrite
+            | AccessType::TessellationControlShaderWrite
+            | AccessType::TessellationEvaluationShaderWrite
+            | AccessType::GeometryShaderWrite
+            | AccessType::FragmentShaderWrite
+            | AccessType::ColorAttachmentWrite
+            | AccessType::DepthStencilAttachmentWrite
+            | AccessType::DepthAttachmentWriteStencilReadOnly
+            | AccessType::StencilAttachmentWriteDepthReadOnly
+            | AccessType::ComputeShaderWrite
+            | AccessType::AnyShaderWrite
+            | AccessType::TransferWrite
+            | AccessType::HostWrite
+            | AccessType::ColorAttachmentReadWrite
+            | AccessType::General
+            | AccessType::AccelerationStructureBuildWrite
+            | AccessType::AccelerationStructureBufferWrite
+    )
+}
+
+#[derive(Debug, Clone)]
+struct ResourceState {
+    accesses: Vec<AccessType>,
+    layout: ImageLayout,
+    /// Where the resource was last used, if it was used by a pass
+    last_use: Option<SchedulePoint>,
+}
+
+impl ResourceState {
+    fn nothing() -> Self {
+        ResourceState {
+            accesses: vec![AccessType::Nothing],
+            layout: ImageLayout::Optimal,
+            last_use: None,
+        }
+    }
+}
+
+/// A position in the schedule.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct SchedulePoint {
+    pub(crate) queue: QueueType,
+    /// Index of the pass, or the number of passes for the end of the schedule
+    pub(crate) pass: usize,
+}
+
+/// Follows the state of every resource along a schedule to find out where barriers are needed.
+///
+/// Images are followed one mip of one layer at a time, buffers as a single subresource.
+#[derive(Debug, Default)]
+pub(crate) struct BarrierTracker {
+    /// The resources that are images, everything else is a buffer
+    images: HashSet<Res>,
+    initial_states: HashMap<Res, ResourceState>,
+    /// Keyed by resource, mip level and array layer
+    states: HashMap<(Res, u32, u32), ResourceState>,
+}
+
+impl BarrierTracker {
+    pub(crate) fn new(images: impl IntoIterator<Item = Res>) -> Self {
+        Self {
+            images: images.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Declare the state a resource is in before the first pass.
+    pub(crate) fn set_initial_state(
+        &mut self,
+        resource: Res,
+        accesses: Vec<AccessType>,
+        layout: ImageLayout,
+    ) {
+        self.initial_states.insert(
+            resource,
+            ResourceState {
+                accesses: or_nothing(accesses),
+                layout,
+                last_use: None,
+            },
+        );
+    }
+
+    /// Every access the last barriers of `resource` don't wait on yet, across its subresources.
+    pub(crate) fn last_accesses(&self, resource: Res) -> Vec<AccessType> {
+        let mut subresources = self
+            .states
+            .iter()
+            .filter(|((res, _, _), _)| *res == resource)
+            .collect::<Vec<_>>();
+        subresources.sort_by_key(|(key, _)| **key);
+
+        let mut accesses = Vec::new();
+        for (_, state) in subresources {
+            for &access in &state.accesses {
+                if !accesses.contains(&access) {
+                    accesses.push(access);
+                }
+            }
+        }
+        accesses
+    }
+
+    /// Move `range` of `resource` to its next state, returning the barriers needed for it.
+    ///
+    /// Consecutive reads in the same layout and on the same queue don't need a barrier, they
+    /// accumulate instead so the next write waits on all of them. Subresources coming from
+    /// the same state share a barrier when they form a range.
+    pub(crate) fn transition(
+        &mut self,
+        resource: Res,
+        range: SubresourceRange,
+        next_accesses: Vec<AccessType>,
+        next_layout: ImageLayout,
+        discard_contents: bool,
+        at: SchedulePoint,
+    ) -> Vec<Barrier> {
+        let next_accesses = or_nothing(next_accesses);
+        // Barriers covering a single subresource, merged afterwards
+        let mut barriers = Vec::<Barrier>::new();
+        let is_image = self.images.contains(&resource);
+
+        for level in range.base_mip_level..range.base_mip_level + range.level_count {
+            for layer in range.base_array_layer..range.base_array_layer + range.layer_count {
+                let initial = &self.initial_states;
+                let state = self
+                    .states
+                    .entry((resource, level, layer))
+                    .or_insert_with(|| {
+                        initial
+                            .get(&resource)
+                            .cloned()
+                            .unwrap_or_else(ResourceState::nothing)
+                    });
+
+                if let Some(mut barrier) = transition_subresource(
+                    state,
+                    resource,
+                    is_image,
+                    &next_accesses,
+                    next_layout,
+                    discard_contents,
+                    at,
+                ) {
+                    barrier.range = SubresourceRange {
+                        base_mip_level: level,
+                        level_count: 1,
+                        base_array_layer: layer,
+                        layer_count: 1,
+                    };
+                    barriers.push(barrier);
+                }
+            }
+        }
+
+        merge_ranges(barriers)
+    }
+}
+
+/// Vulkan has no state for an empty list of accesses, it stands for [AccessType::Nothing].
+fn or_nothing(accesses: Vec<AccessType>) -> Vec<AccessType> {
+    if accesses.is_empty() {
+        vec![AccessType::Nothing]
+    } else {
+        accesses
+    }
+}
+
+fn transition_subresource(
+    state: &mut ResourceState,
+    resource: Res,
+    is_image: bool,
+    next_accesses: &[AccessType],
+    next_layout: ImageLayout,
+    discard_contents: bool,
+    at: SchedulePoint,
+) -> Option<Barrier> {
+    let is_first_use = state.accesses == [AccessType::Nothing];
+    let hazard =
+        state.accesses.iter().copied().any(is_write) || next_accesses.iter().copied().any(is_write);
+    let queue_transfer = state
+        .last_use
+        .filter(|last| last.queue != at.queue)
+        .map(|last| QueueTransfer {
+            from: last.queue,
+            to: at.queue,
+            release_after: last.pass,
+        });
+
+    // Different reads may still need different layouts under the optimal one
+    let same_layout = state.layout == next_layout
+        && (!is_image
+            || next_layout != ImageLayout::Optimal
+            || next_accesses.iter().chain(&state.accesses).all(|&access| {
+                optimal_read_layout(access) == optimal_read_layout(state.accesses[0])
+            }));
+
+    if !is_first_use && !hazard && queue_transfer.is_none() && same_layout {
+        for &access in next_accesses {
+            if !state.accesses.contains(&access) {
+                state.accesses.push(access);
+            }
+        }
+        state.last_use = Some(at);
+        return None;
+    }
+
+    let barrier = Barrier {
+        resource,
+        range: SubresourceRange::default(),
+        previous_accesses: std::mem::replace(&mut state.accesses, next_accesses.to_vec()),
+        next_accesses: next_accesses.to_vec(),
+        previous_layout: state.layout,
+        next_layout,
+        discard_contents: discard_contents || is_first_use,
+        queue_transfer,
+    };
+    state.layout = next_layout;
+    state.last_use = Some(at);
+
+    Some(barrier)
+}
+
+/// Merge the barriers of single subresources into as few ranges as possible: first the
+/// consecutive layers of a mip, then the consecutive mips covering the same layers.
+///
+/// `barriers` must be sorted by mip then layer, which the result stays.
+fn merge_ranges(barriers: Vec<Barrier>) -> Vec<Barrier> {
+    let same_state = |a: &Barrier, b: &Barrier| {
+        Barrier {
+            range: a.range,
+            ..b.clone()
+        } == *a
+    };
+
+    let mut layers = Vec::<Barrier>::new();
+    for barrier in barriers {
+        match layers.last_mut() {
+            Some(last)
+                if same_state(last, &barrier)
+                    && last.range.base_mip_level == barrier.range.base_mip_level
+                    && last.range.base_array_layer + last.range.layer_count
+                        == barrier.range.base_array_layer =>
+            {
+                last.range.layer_count += 1;
+            }
+            _ => layers.push(barrier),
+        }
+    }
+
+    let mut merged = Vec::<Barrier>::new();
+    for barrier in layers {
+        let previous_mip = merged.iter_mut().rev().find(|last| {
+            same_state(last, &barrier)
+                && last.range.base_mip_level + last.range.level_count
+                    == barrier.range.base_mip_level
+                && last.range.base_array_layer == barrier.range.base_array_layer
+                && last.range.layer_count == barrier.range.layer_count
+        });
+        match previous_mip {
+            Some(last) => last.range.level_count += 1,
+            None => merged.push(barrier),
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use vk_sync_fork::{AccessType, ImageLayout};
+
+    use crate::{
+        barriers::{read_accesses, write_accesses, PassKind},
+        tests::{compile, Graph, IMAGE},
+        ExternalState, ImageDesc, ReadActionFlags, SubresourceRange, WriteActionFlags,
+    };
+
+    #[test]
+    fn access_mapping_depends_on_pass_kind() {
+        assert_eq!(
+            read_accesses(PassKind::Compute, ReadActionFlags::STORAGE),
+            [AccessType::ComputeShaderReadOther]
+        );
+        assert_eq!(
+            write_accesses(PassKind::Raster, WriteActionFlags::STORAGE),
+            [AccessType::AnyShaderWrite]
+        );
+        assert_eq!(
+            write_accesses(PassKind::Raster, WriteActionFlags::DEPTH_ATTACHMENT),
+            [AccessType::DepthStencilAttachmentWrite]
+        );
+    }
+
+    #[test]
+    fn barriers_between_passes() {
+        let mut graph = Graph::new();
+
+        let texture = graph.register_resource_image("Texture", IMAGE);
+        let color = graph.register_resource_image("Color", IMAGE);
+        let depth = graph.register_resource_image("Depth", IMAGE);
+
+        graph.copy_from_host(texture);
+        graph
+            .create_raster_pass("Prepass")
+            .add_depth_attachment(depth, false);
+        graph
+            .create_raster_pass("Main")
+            .add_sampled(texture)
+            .add_depth_attachment(depth, true)
+            .add_color_attachment(color);
+        graph.copy_to_host(color);
+
+        let compiled = compile(&graph);
+        let main = &compiled.passes()[1];
+
+        let sampled = main
+            .barriers
+            .iter()
+            .find(|b| b.resource == texture)
+            .unwrap();
+        assert_eq!(sampled.previous_accesses, [AccessType::TransferWrite]);
+        assert_eq!(
+            sampled.next_accesses,
+            [AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer]
+        );
+        assert!(!sampled.discard_contents);
+
+        let depth = main.barriers.iter().find(|b| b.resource == depth).unwrap();
+        assert_eq!(
+            depth.previous_accesses,
+            [AccessType::DepthStencilAttachmentWrite]
+        );
+        assert!(!depth.discard_contents);
+
+        let color_barrier = main.barriers.iter().find(|b| b.resource == color).unwrap();
+        assert_eq!(color_barrier.previous_accesses, [AccessType::Nothing]);
+        assert!(color_barrier.discard_contents);
+
+        let readback = &compiled.final_barriers()[0];
+        assert_eq!(readback.resource, color);
+        assert_eq!(
+            readback.previous_accesses,
+            [AccessType::ColorAttachmentWrite]
+        );
+        assert_eq!(readback.next_accesses, [AccessType::TransferRead]);
+    }
+
+    #[test]
+    fn storage_images_use_general_layout() {
+        let mut graph = Graph::new();
+
+        let image = graph.register_resource_image("Image", IMAGE);
+        let output = graph.register_resource_image("Output", IMAGE);
+
+        graph.create_raster_pass("Draw").add_color_attachment(image);
+        graph
+            .create_compute_pass("Post")
+            .add_input_storage_buffer(image)
+            .add_output_storage_buffer(output);
+        graph.copy_to_host(output);
+
+        let compiled = compile(&graph);
+        let post = &compiled.passes()[1];
+        let barrier = post.barriers.iter().find(|b| b.resource == image).unwrap();
+
+        assert_eq!(barrier.previous_layout, ImageLayout::Optimal);
+        assert_eq!(barrier.next_layout, ImageLayout::General);
+    }
+
+    #[test]
+    fn consecutive_reads_accumulate() {
+        let mut graph = Graph::new();
+
+        let shadow = graph.register_resource_image("Shadow", IMAGE);
+        let a = graph.register_resource_image("A", IMAGE);
+        let b = graph.register_resource_image("B", IMAGE);
+
+        graph
+            .create_raster_pass("Shadow")
+            .add_depth_attachment(shadow, false);
+        graph
+            .create_raster_pass("A")
+            .add_sampled(shadow)
+            .add_color_attachment(a);
+        graph
+            .create_raster_pass("B")
+            .add_sampled(shadow)
+            .add_color_attachment(b);
+        graph
+            .create_raster_pass("Clear shadow")
+            .add_depth_attachment(shadow, false);
+        graph.copy_to_host(a);
+        graph.copy_to_host(b);
+        graph.copy_to_host(shadow);
+
+        let compiled = compile(&graph);
+        let passes = compiled.passes();
+
+        assert!(passes[2].barriers.iter().all(|b| b.resource != shadow));
+
+        let rewrite = passes[3]
+            .barriers
+            .iter()
+            .find(|b| b.resource == shadow)
+            .unwrap();
+        assert_eq!(
+            rewrite.previous_accesses,
+            [AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer]
+        );
+        assert!(rewrite.discard_contents);
+    }
+
+    #[test]
+    fn ray_tracing_barriers() {
+        let mut graph = Graph::new();
+
+        let vertices = graph.register_resource_buffer("Vertices", 1024);
+        let instances = graph.register_resource_buffer("Instances", 64);
+        let scratch = graph.register_resource_buffer("Scratch", 4096);
+        let blas = graph.register_resource_buffer("BLAS", 4096);
+        let tlas = graph.register_resource_buffer("TLAS", 4096);
+        let traced = graph.register_resource_image("Traced", IMAGE);
+        let output = graph.register_resource_image("Output", IMAGE);
+
+        graph.copy_from_host(vertices);
+        graph.copy_from_host(instances);
+        graph
+            .create_acceleration_structure_build_pass("BLAS")
+            .add_geometry_buffer(vertices)
+            .add_scratch_buffer(scratch)
+            .add_output_acceleration_structure(blas, false);
+        graph
+            .create_acceleration_structure_build_pass("TLAS")
+            .add_geometry_buffer(instances)
+            .add_input_acceleration_structure(blas)
+            .add_output_acceleration_structure(tlas, false);
+        graph
+            .create_ray_tracing_pass("Trace")
+            .add_acceleration_structure(tlas)
+            .add_output_storage_image(traced);
+        graph
+            .create_transfer_pass("Blit")
+            .add_source(traced)
+            .add_destination(output, false);
+        graph.copy_to_host(output);
+
+        let compiled = compile(&graph);
+        let [build_blas, build_tlas, trace, blit] = match compiled.passes() {
+            [a, b, c, d] => [a, b, c, d],
+            _ => panic!("Wrong number of passes"),
+        };
+        assert_eq!(build_blas.kind, PassKind::AccelerationStructureBuild);
+        assert_eq!(blit.kind, PassKind::Transfer);
+
+        let geometry = build_blas
+            .barriers
+            .iter()
+            .find(|b| b.resource == vertices)
+            .unwrap();
+        assert_eq!(
+            geometry.next_accesses,
+            [AccessType::AccelerationStructureBuildRead]
+        );
+
+        let blas_read = build_tlas
+            .barriers
+            .iter()
+            .find(|b| b.resource == blas)
+            .unwrap();
+        assert_eq!(
+            blas_read.previous_accesses,
+            [AccessType::AccelerationStructureBuildWrite]
+        );
+        assert_eq!(
+            blas_read.next_accesses,
+            [AccessType::AccelerationStructureBuildRead]
+        );
+
+        let tlas_read = trace.barriers.iter().find(|b| b.resource == tlas).unwrap();
+        assert_eq!(
+            tlas_read.next_accesses,
+            [AccessType::RayTracingShaderReadAccelerationStructure]
+        );
+
+        let storage = trace
+            .barriers
+            .iter()
+            .find(|b| b.resource == traced)
+            .unwrap();
+        assert_eq!(storage.next_accesses, [AccessType::AnyShaderWrite]);
+        assert_eq!(storage.next_layout, ImageLayout::General);
+
+        let source = blit.barriers.iter().find(|b| b.resource == traced).unwrap();
+        assert_eq!(source.previous_layout, ImageLayout::General);
+        assert_eq!(source.next_accesses, [AccessType::TransferRead]);
+        assert_eq!(source.next_layout, ImageLayout::Optimal);
+
+        assert_eq!(
+            graph.buffer_usage(tlas),
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+        );
+        assert!(graph
+            .buffer_usage(scratch)
+            .contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS));
+    }
+
+    #[test]
+    fn imported_backbuffer_is_kept_and_transitioned() {
+        let mut graph = Graph::new();
+
+        let backbuffer = graph.import_image(
+            "Backbuffer",
+            IMAGE,
+            ExternalState::new(AccessType::Nothing),
+            ExternalState::new(AccessType::Present),
+        );
+        graph
+            .create_raster_pass("Clear")
+            .add_color_attachment(backbuffer);
+
+        let compiled = compile(&graph);
+        assert_eq!(compiled.passes().len(), 1);
+        assert!(!graph.is_transient(backbuffer));
+
+        let clear = &compiled.passes()[0].barriers[0];
+        assert_eq!(clear.previous_accesses, [AccessType::Nothing]);
+        assert!(clear.discard_contents);
+
+        let present = &compiled.final_barriers()[0];
+        assert_eq!(present.resource, backbuffer);
+        assert_eq!(
+            present.previous_accesses,
+            [AccessType::ColorAttachmentWrite]
+        );
+        assert_eq!(present.next_accesses, [AccessType::Present]);
+    }
+
+    #[test]
+    fn imported_without_accesses_is_undefined() {
+        let mut graph = Graph::new();
+
+        let nothing = ExternalState {
+            accesses: vec![],
+            layout: ImageLayout::Optimal,
+        };
+        let image = graph.import_image("Image", IMAGE, nothing.clone(), nothing);
+        graph
+            .create_raster_pass("Clear")
+            .add_color_attachment(image);
+
+        let compiled = compile(&graph);
+
+        let clear = &compiled.passes()[0].barriers[0];
+        assert_eq!(clear.previous_accesses, [AccessType::Nothing]);
+        assert!(clear.discard_contents);
+        assert_eq!(
+            compiled.final_barriers()[0].next_accesses,
+            [AccessType::Nothing]
+        );
+    }
+
+    #[test]
+    fn imported_texture_starts_in_its_current_state() {
+        let mut graph = Graph::new();
+
+        let sampled = ExternalState::new(AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer);
+        let texture = graph.import_image("Texture", IMAGE, sampled.clone(), sampled);
+        let color = graph.register_resource_image("Color", IMAGE);
+        graph
+            .create_raster_pass("Main")
+            .add_sampled(texture)
+            .add_color_attachment(color);
+        graph.copy_to_host(color);
+
+        let compiled = compile(&graph);
+
+        // Already readable and left as it was found
+        assert!(compiled.passes()[0]
+            .barriers
+            .iter()
+            .all(|barrier| barrier.resource != texture));
+        assert!(compiled
+            .final_barriers()
+            .iter()
+            .all(|barrier| barrier.resource != texture));
+        assert!(graph.validate().is_empty());
+    }
+
+    #[test]
+    fn mips_are_tracked_separately() {
+        let mut graph = Graph::new();
+
+        let texture = graph.register_resource_image(
+            "Texture",
+            ImageDesc {
+                mip_levels: 3,
+                ..IMAGE
+            },
+        );
+        let color = graph.register_resource_image("Color", IMAGE);
+
+        graph.copy_from_host(texture);
+        for level in 1..3 {
+            graph
+                .create_transfer_pass("Blit")
+                .add_source_range(texture, SubresourceRange::mip(level - 1))
+                .add_destination_range(texture, SubresourceRange::mip(level), false);
+        }
+        graph
+            .create_raster_pass("Main")
+            .add_sampled(texture)
+            .add_color_attachment(color);
+        graph.copy_to_host(color);
+
+        let compiled = compile(&graph);
+        assert!(graph.validate().is_empty());
+
+        let first_blit = &compiled.passes()[0].barriers;
+        assert_eq!(first_blit.len(), 2);
+        assert_eq!(first_blit[0].range, SubresourceRange::mip(0));
+        assert_eq!(first_blit[0].next_accesses, [AccessType::TransferRead]);
+        assert_eq!(first_blit[1].range, SubresourceRange::mip(1));
+        assert!(first_blit[1].discard_contents);
+
+        // The mips read by the blits share a barrier, the last one was only written
+        let main = &compiled.passes()[2].barriers;
+        assert_eq!(main.len(), 3);
+        assert_eq!(
+            main[0].range,
+            SubresourceRange {
+                level_count: 2,
+                ..SubresourceRange::mip(0)
+            }
+        );
+        assert_eq!(main[0].previous_accesses, [AccessType::TransferRead]);
+        assert_eq!(main[1].range, SubresourceRange::mip(2));
+        assert_eq!(main[1].previous_accesses, [AccessType::TransferWrite]);
+        assert_eq!(main[2].resource, color);
+    }
+}
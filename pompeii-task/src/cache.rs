@@ -0,0 +1,342 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use ash::vk;
+use petgraph::{prelude::NodeIndex, visit::EdgeRef};
+
+use crate::{
+    barriers::PassKind, conditions::Disabled, errors::TaskGraphError, ActionTy, CompiledGraph,
+    EdgeAction, ExternalState, History, ImageDesc, Import, NodeId, ResourceTy, SubresourceRange,
+    TaskGraph,
+};
+
+/// Everything compiling a graph depends on, compared to tell whether a compiled graph can be
+/// reused.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Structure {
+    nodes: Vec<StructureNode>,
+    /// Sorted by edge index
+    edges: Vec<(NodeIndex, NodeIndex, EdgeAction, Option<SubresourceRange>)>,
+    /// Sorted by resource
+    imports: Vec<(NodeIndex, Import)>,
+    histories: Vec<History>,
+    disabled: Disabled,
+    local_read: bool,
+}
+
+#[derive(Debug, PartialEq, Hash)]
+enum StructureNode {
+    Buffer(vk::DeviceSize),
+    Image(ImageDesc),
+    External,
+    Pass(PassKind),
+}
+
+impl Hash for Structure {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.nodes.hash(state);
+        self.edges.hash(state);
+        self.imports.len().hash(state);
+        for (resource, import) in &self.imports {
+            resource.hash(state);
+            hash_state(&import.current, state);
+            hash_state(&import.final_state, state);
+        }
+        self.histories.hash(state);
+        self.disabled.hash(state);
+        self.local_read.hash(state);
+    }
+}
+
+impl<R, C> TaskGraph<R, C> {
+    /// A hash of everything compiling the graph depends on: the kind of every pass, the
+    /// description of every resource, the edges between them, the imports and the histories.
+    ///
+    /// The passes currently disabled by their condition are part of it. Tags and record
+    /// callbacks are left out, two graphs with the same structure compile to the same schedule
+    /// and can run on the same allocations.
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.structure().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub(crate) fn structure(&self) -> Structure {
+        let nodes = self
+            .graph
+            .node_indices()
+            .map(|node| match &self.graph[node] {
+                NodeId::Resource(ResourceTy::Buffer(_, size)) => StructureNode::Buffer(*size),
+                NodeId::Resource(ResourceTy::Image(_, desc)) => StructureNode::Image(*desc),
+                NodeId::Action(ActionTy::External) => StructureNode::External,
+                NodeId::Action(_) => StructureNode::Pass(self.pass_kind(node)),
+            })
+            .collect();
+
+        let mut edges = self.graph.edge_references().collect::<Vec<_>>();
+        edges.sort_by_key(|edge| edge.id());
+        let edges = edges
+            .into_iter()
+            .map(|edge| {
+                (
+                    edge.source(),
+                    edge.target(),
+                    edge.weight().clone(),
+                    self.ranges.get(&edge.id()).copied(),
+                )
+            })
+            .collect();
+
+        let imports = self
+            .sorted_imports()
+            .into_iter()
+            .map(|(resource, import)| (resource.0, import.clone()))
+            .collect();
+
+        Structure {
+            nodes,
+            edges,
+            imports,
+            histories: self.histories.clone(),
+            disabled: self.disabled(),
+            local_read: self.local_read,
+        }
+    }
+}
+
+fn hash_state<H: Hasher>(state: &ExternalState, hasher: &mut H) {
+    // Both are plain enums which don't implement Hash
+    state.accesses.len().hash(hasher);
+    for &access in &state.accesses {
+        (access as u32).hash(hasher);
+    }
+    (state.layout as u32).hash(hasher);
+}
+
+/// How often a [CompiledGraphCache] could skip compiling.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Keeps the last compiled graph, along with whatever was allocated to run it, for as long as
+/// the graphs it is given keep the same [structure](TaskGraph::structural_hash).
+#[derive(Debug)]
+pub struct CompiledGraphCache<T> {
+    cached: Option<CachedGraph<T>>,
+    stats: CacheStats,
+}
+
+#[derive(Debug)]
+struct CachedGraph<T> {
+    structure: Structure,
+    compiled: CompiledGraph,
+    allocations: T,
+}
+
+impl<T> Default for CompiledGraphCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> CompiledGraphCache<T> {
+    pub fn new() -> Self {
+        Self {
+            cached: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// The compiled graph and allocations for `graph`, which are only compiled and allocated
+    /// again when its structure changed since the last call.
+    ///
+    /// On a miss, `allocate` is given the new compiled graph and the allocations of the
+    /// previous one, to take and free or recycle. If compiling fails, the previous graph stays
+    /// cached, as it does when allocating fails without taking its allocations.
+    pub fn get_or_compile<R, C, E: From<TaskGraphError>>(
+        &mut self,
+        graph: &TaskGraph<R, C>,
+        allocate: impl FnOnce(&CompiledGraph, &mut Option<T>) -> Result<T, E>,
+    ) -> Result<(&CompiledGraph, &mut T), E> {
+        let structure = graph.structure();
+
+        if self.cached.as_ref().map(|cached| &cached.structure) == Some(&structure) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+
+            let compiled = graph.compile_without(&structure.disabled)?;
+            let (previous, mut previous_allocations) = match self.cached.take() {
+                Some(cached) => (
+                    Some((cached.structure, cached.compiled)),
+                    Some(cached.allocations),
+                ),
+                None => (None, None),
+            };
+
+            match allocate(&compiled, &mut previous_allocations) {
+                Ok(allocations) => {
+                    self.cached = Some(CachedGraph {
+                        structure,
+                        compiled,
+                        allocations,
+                    })
+                }
+                Err(err) => {
+                    if let (Some((structure, compiled)), Some(allocations)) =
+                        (previous, previous_allocations)
+                    {
+                        self.cached = Some(CachedGraph {
+                            structure,
+                            compiled,
+                            allocations,
+                        });
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let cached = self.cached.as_mut().unwrap();
+        Ok((&cached.compiled, &mut cached.allocations))
+    }
+
+    /// The last compiled graph and its allocations, if any.
+    pub fn get_mut(&mut self) -> Option<(&CompiledGraph, &mut T)> {
+        self.cached
+            .as_mut()
+            .map(|cached| (&cached.compiled, &mut cached.allocations))
+    }
+
+    /// Empty the cache, handing back the allocations to free.
+    pub fn take(&mut self) -> Option<T> {
+        self.cached.take().map(|cached| cached.allocations)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use petgraph::prelude::NodeIndex;
+
+    use crate::{
+        errors::TaskGraphError,
+        tests::{Graph, IMAGE},
+        Action, CacheStats, CompiledGraphCache, ImageDesc,
+    };
+
+    fn frame(tag: &'static str, desc: ImageDesc) -> Graph {
+        let mut graph = Graph::new();
+        let color = graph.register_resource_image(tag, desc);
+        graph.create_raster_pass(tag).add_color_attachment(color);
+        graph.copy_to_host(color);
+        graph
+    }
+
+    #[test]
+    fn tags_are_not_part_of_the_structure() {
+        assert_eq!(
+            frame("A", IMAGE).structural_hash(),
+            frame("B", IMAGE).structural_hash()
+        );
+        assert_ne!(
+            frame("A", IMAGE).structural_hash(),
+            frame("A", ImageDesc::new_2d(1280, 720, IMAGE.format)).structural_hash()
+        );
+
+        let mut preserved = frame("A", IMAGE);
+        let color = preserved.register_resource_image("Color", IMAGE);
+        let mut discarded = frame("A", IMAGE);
+        discarded.register_resource_image("Color", IMAGE);
+        preserved
+            .create_transfer_pass("Clear")
+            .add_destination(color, true);
+        discarded
+            .create_transfer_pass("Clear")
+            .add_destination(color, false);
+        assert_ne!(preserved.structural_hash(), discarded.structural_hash());
+    }
+
+    #[test]
+    fn allocations_are_reused_until_the_structure_changes() {
+        let mut cache = CompiledGraphCache::new();
+        let mut allocated = 0;
+        let mut allocate = |_: &_, previous: &mut Option<u32>| -> Result<u32, TaskGraphError> {
+            allocated += 1;
+            Ok(previous.take().map_or(0, |previous| previous + 1))
+        };
+
+        let (_, allocations) = cache
+            .get_or_compile(&frame("A", IMAGE), &mut allocate)
+            .unwrap();
+        assert_eq!(*allocations, 0);
+        let (compiled, allocations) = cache
+            .get_or_compile(&frame("B", IMAGE), &mut allocate)
+            .unwrap();
+        assert_eq!(*allocations, 0);
+        assert_eq!(compiled.passes().len(), 1);
+
+        let resized = ImageDesc::new_2d(1280, 720, IMAGE.format);
+        let (_, allocations) = cache
+            .get_or_compile(&frame("A", resized), &mut allocate)
+            .unwrap();
+        assert_eq!(*allocations, 1);
+
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2 });
+        assert_eq!(allocated, 2);
+        assert_eq!(cache.take(), Some(1));
+    }
+
+    #[test]
+    fn failed_allocations_keep_the_previous_graph() {
+        let mut cache = CompiledGraphCache::new();
+        cache
+            .get_or_compile(&frame("A", IMAGE), |_, _| Ok::<_, TaskGraphError>(7))
+            .unwrap();
+
+        let resized = frame("A", ImageDesc::new_2d(1280, 720, IMAGE.format));
+        let failed = cache.get_or_compile(&resized, |_, _| {
+            Err::<u32, _>(TaskGraphError::Cycle(Action(NodeIndex::new(0))))
+        });
+        assert!(failed.is_err());
+
+        let (compiled, allocations) = cache.get_mut().unwrap();
+        assert_eq!(compiled.passes().len(), 1);
+        assert_eq!(*allocations, 7);
+    }
+
+    #[test]
+    fn conditions_run_once_per_compilation() {
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let mut graph = frame("A", IMAGE);
+        let color = graph.register_resource_image("Overlay", IMAGE);
+        let counted = runs.clone();
+        graph
+            .create_raster_pass("Overlay")
+            .add_color_attachment(color)
+            .enable_if(move || {
+                counted.fetch_add(1, Ordering::Relaxed);
+                true
+            });
+        graph.copy_to_host(color);
+
+        let mut cache = CompiledGraphCache::new();
+        cache
+            .get_or_compile(&graph, |_, _| Ok::<_, TaskGraphError>(()))
+            .unwrap();
+        assert_eq!(runs.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,744 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    ops::Range,
+};
+
+use petgraph::{prelude::NodeIndex, visit::EdgeRef, Direction};
+use vk_sync_fork::{AccessType, ImageLayout};
+
+use crate::{
+    aliasing::ResourceLifetime,
+    barriers::{
+        image_layout, read_accesses, write_accesses, Barrier, BarrierTracker, PassKind,
+        SchedulePoint,
+    },
+    conditions::Disabled,
+    errors::{Result, TaskGraphError},
+    merge::{hoist_barriers, shared_resources, split_at_releases},
+    queues::{split_submissions, QueueType, Submission},
+    Action, ActionTy, EdgeAction, ExternalState, History, Import, NodeId, ReadActionFlags, Res,
+    ResourceTy, SubresourceRange, TaskGraph, WriteActionFlags,
+};
+
+/// Linear schedule of the passes of a [TaskGraph].
+#[derive(Debug, Clone)]
+pub struct CompiledGraph {
+    passes: Vec<CompiledPass>,
+    final_barriers: Vec<Barrier>,
+    culled: Vec<Action>,
+    submissions: Vec<Submission>,
+    histories: Vec<(History, ExternalState)>,
+    forwarded: Vec<(Res, Res)>,
+    pub(crate) render_groups: Vec<Range<usize>>,
+    pub(crate) lifetimes: Vec<ResourceLifetime>,
+}
+
+impl CompiledGraph {
+    /// The passes in execution order.
+    pub fn passes(&self) -> &[CompiledPass] {
+        &self.passes
+    }
+
+    /// The passes grouped by queue, in submission order.
+    pub fn submissions(&self) -> &[Submission] {
+        &self.submissions
+    }
+
+    /// Barriers to record on the graphics queue after the last pass, to hand resources back
+    /// to the host and imported resources back in their final state.
+    pub fn final_barriers(&self) -> &[Barrier] {
+        &self.final_barriers
+    }
+
+    /// The state each history resource is left in at the end of a frame and expected in at
+    /// the start of the next one, in declaration order.
+    pub fn history_states(&self) -> &[(History, ExternalState)] {
+        &self.histories
+    }
+
+    /// The passes left out because nothing they produce is used or because they are
+    /// disabled, in declaration order.
+    pub fn culled_passes(&self) -> &[Action] {
+        &self.culled
+    }
+
+    /// Outputs of disabled passthrough passes, along with the resource the passes use in
+    /// their place. Both must resolve to the same Vulkan objects.
+    pub fn forwarded_resources(&self) -> &[(Res, Res)] {
+        &self.forwarded
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledPass {
+    pub action: Action,
+    pub kind: PassKind,
+    pub queue: QueueType,
+    pub reads: Vec<PassRead>,
+    pub writes: Vec<PassWrite>,
+    /// Barriers to record right before the pass
+    pub barriers: Vec<Barrier>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PassRead {
+    pub resource: Res,
+    /// The mips and layers read, all of them unless the edge says otherwise
+    pub range: SubresourceRange,
+    pub flags: ReadActionFlags,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PassWrite {
+    pub resource: Res,
+    /// The mips and layers written, all of them unless the edge says otherwise
+    pub range: SubresourceRange,
+    pub flags: WriteActionFlags,
+    /// Whether the previous content of the resource must be kept
+    pub preserve: bool,
+}
+
+impl<R, C> TaskGraph<R, C> {
+    /// Sort the passes into a linear schedule and infer the barriers needed around them.
+    ///
+    /// When a resource is written by several passes, its accesses happen in declaration order.
+    /// Its writers must be ordered by a read in between or by a write preserving the previous
+    /// content, compiling fails with [UnorderedWrites](TaskGraphError::UnorderedWrites)
+    /// otherwise. A pass reading a resource before any of its writers is declared waits for all
+    /// of them. Independent passes keep their declaration order.
+    ///
+    /// Passes that don't contribute to anything read by the host, to an imported resource or
+    /// to the next frame through a history resource are culled.
+    ///
+    /// Disabled passes are culled along with the passes using what they write, unless they
+    /// forward it with a passthrough.
+    pub fn compile(&self) -> Result<CompiledGraph> {
+        self.compile_without(&self.disabled())
+    }
+
+    /// Compile with the passes found disabled beforehand, without running their conditions
+    /// again.
+    pub(crate) fn compile_without(&self, disabled: &Disabled) -> Result<CompiledGraph> {
+        if disabled.passes.is_empty() {
+            return self.compile_enabled();
+        }
+
+        let mut compiled = self.without_disabled(disabled).compile_enabled()?;
+        compiled.forwarded = disabled
+            .forwards
+            .iter()
+            .map(|(&output, &input)| (Res(output), Res(input)))
+            .collect();
+        Ok(compiled)
+    }
+
+    fn compile_enabled(&self) -> Result<CompiledGraph> {
+        if let Some(&(first, second, resource)) = self.unordered_write_pairs().first() {
+            return Err(TaskGraphError::UnorderedWrites(
+                Action(first),
+                Action(second),
+                Res(resource),
+            ));
+        }
+
+        let live = self.live_passes();
+        let culled = self
+            .pass_nodes()
+            .filter(|pass| !live.contains(pass))
+            .map(Action)
+            .collect();
+
+        let mut tracker = BarrierTracker::new(
+            self.graph
+                .node_indices()
+                .map(Res)
+                .filter(|&res| self.is_image(res)),
+        );
+
+        // Uploaded resources start in the state the upload left them
+        for upload in self
+            .graph
+            .edges_directed(self.external_before_node, Direction::Outgoing)
+        {
+            if let EdgeAction::Write(flags, _) = *upload.weight() {
+                tracker.set_initial_state(
+                    Res(upload.target()),
+                    write_accesses(PassKind::Upload, flags),
+                    ImageLayout::Optimal,
+                );
+            }
+        }
+
+        let imports = self.sorted_imports();
+        for (resource, import) in &imports {
+            tracker.set_initial_state(
+                *resource,
+                import.current.accesses.clone(),
+                import.current.layout,
+            );
+        }
+
+        let order = self.sort_passes(&live)?;
+        let queues = self.assign_queues(&order);
+
+        let mut passes = order
+            .into_iter()
+            .zip(queues)
+            .map(|(pass, queue)| self.compile_pass(pass, queue))
+            .collect::<Vec<_>>();
+
+        // Both halves of a history come from the previous frame in the same state, owned by the
+        // graphics queue their passes run on
+        let histories = self.between_frame_states(&passes);
+        for (history, state) in &histories {
+            for resource in [history.current, history.previous] {
+                tracker.set_initial_state(resource, state.accesses.clone(), state.layout);
+            }
+        }
+
+        let groups = self.render_groups(&passes);
+        let shared = groups
+            .iter()
+            .map(|group| (group.clone(), shared_resources(&passes[group.clone()])))
+            .collect::<Vec<_>>();
+
+        for (i, pass) in passes.iter_mut().enumerate() {
+            let general = shared
+                .iter()
+                .find(|(group, _)| group.contains(&i))
+                .map(|(_, resources)| resources);
+            pass.barriers = self.pass_barriers(&mut tracker, pass, i, general);
+        }
+
+        // Downloaded resources need to be readable by the transfer
+        let mut downloads = self
+            .graph
+            .edges_directed(self.external_after_node, Direction::Incoming)
+            .collect::<Vec<_>>();
+        downloads.sort_by_key(|edge| edge.id());
+
+        let end = SchedulePoint {
+            queue: QueueType::Graphics,
+            pass: passes.len(),
+        };
+        let mut final_barriers = downloads
+            .into_iter()
+            .flat_map(|download| match *download.weight() {
+                EdgeAction::Read(flags) => tracker.transition(
+                    Res(download.source()),
+                    self.full_range(Res(download.source())),
+                    read_accesses(PassKind::Upload, flags),
+                    ImageLayout::Optimal,
+                    false,
+                    end,
+                ),
+                _ => Vec::new(),
+            })
+            .collect::<Vec<_>>();
+
+        // Imported resources are handed back in the state their owner expects
+        final_barriers.extend(imports.into_iter().flat_map(|(resource, import)| {
+            tracker.transition(
+                resource,
+                self.full_range(resource),
+                import.final_state.accesses.clone(),
+                import.final_state.layout,
+                false,
+                end,
+            )
+        }));
+
+        // And histories back in the state the next frame expects
+        for (history, state) in &histories {
+            for resource in [history.current, history.previous] {
+                final_barriers.extend(tracker.transition(
+                    resource,
+                    self.full_range(resource),
+                    state.accesses.clone(),
+                    state.layout,
+                    false,
+                    end,
+                ));
+            }
+        }
+
+        let render_groups = split_at_releases(groups, &passes, &final_barriers);
+        hoist_barriers(&mut passes, &render_groups);
+
+        Ok(CompiledGraph {
+            render_groups,
+            histories,
+            lifetimes: self.transient_lifetimes(&passes, &tracker),
+            submissions: split_submissions(&passes),
+            passes,
+            final_barriers,
+            culled,
+            forwarded: Vec::new(),
+        })
+    }
+
+    pub fn is_image(&self, Res(res): Res) -> bool {
+        matches!(self.graph[res], NodeId::Resource(ResourceTy::Image(..)))
+    }
+
+    pub(crate) fn pass_kind(&self, pass: NodeIndex) -> PassKind {
+        match self.graph[pass] {
+            NodeId::Action(ActionTy::Raster(_)) => PassKind::Raster,
+            NodeId::Action(ActionTy::Compute(_)) => PassKind::Compute,
+            NodeId::Action(ActionTy::RayTracing(_)) => PassKind::RayTracing,
+            NodeId::Action(ActionTy::Transfer(_)) => PassKind::Transfer,
+            NodeId::Action(ActionTy::AccelerationStructureBuild(_)) => {
+                PassKind::AccelerationStructureBuild
+            }
+            _ => unreachable!("Not a pass"),
+        }
+    }
+
+    /// The imported resources, in declaration order.
+    pub(crate) fn sorted_imports(&self) -> Vec<(Res, &Import)> {
+        let mut imports = self
+            .imports
+            .iter()
+            .map(|(&node, import)| (Res(node), import))
+            .collect::<Vec<_>>();
+        imports.sort_by_key(|(resource, _)| *resource);
+        imports
+    }
+
+    /// Every node that is a pass, the external nodes excluded.
+    pub(crate) fn pass_nodes(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.graph.node_indices().filter(|&node| {
+            matches!(self.graph[node], NodeId::Action(_))
+                && node != self.external_before_node
+                && node != self.external_after_node
+        })
+    }
+
+    /// The passes that must run before `pass`, possibly with duplicates.
+    ///
+    /// Only the accesses to overlapping ranges of a resource matter.
+    pub(crate) fn pass_dependencies(&self, pass: NodeIndex) -> Vec<NodeIndex> {
+        let mut dependencies = Vec::new();
+
+        // Writers of what we read, only the ones declared before us if there are any
+        for read in self.graph.edges_directed(pass, Direction::Incoming) {
+            let range = self.edge_range(read.id(), Res(read.source()));
+            let writers = self
+                .overlapping_writers(read.source(), range)
+                .filter(|&writer| writer != pass)
+                .collect::<Vec<_>>();
+            if writers.iter().any(|&writer| writer < pass) {
+                dependencies.extend(writers.into_iter().filter(|&writer| writer < pass));
+            } else {
+                dependencies.extend(writers);
+            }
+        }
+
+        for write in self.graph.edges_directed(pass, Direction::Outgoing) {
+            let resource = write.target();
+            let range = self.edge_range(write.id(), Res(resource));
+
+            // Readers of the previous content we are about to overwrite
+            dependencies.extend(self.overlapping_readers(resource, range).filter(|&reader| {
+                reader < pass
+                    && self
+                        .overlapping_writers(resource, range)
+                        .any(|writer| writer < reader)
+            }));
+
+            // Previous writers of what we write on top of
+            if let EdgeAction::Write(_, true) = write.weight() {
+                dependencies.extend(
+                    self.overlapping_writers(resource, range)
+                        .filter(|&writer| writer < pass),
+                );
+            }
+        }
+
+        dependencies
+    }
+
+    /// The passes writing to some part of `range` of `resource`.
+    fn overlapping_writers(
+        &self,
+        resource: NodeIndex,
+        range: SubresourceRange,
+    ) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.graph
+            .edges_directed(resource, Direction::Incoming)
+            .filter(move |edge| {
+                edge.source() != self.external_before_node
+                    && self.edge_range(edge.id(), Res(resource)).overlaps(&range)
+            })
+            .map(|edge| edge.source())
+    }
+
+    /// The passes reading from some part of `range` of `resource`.
+    fn overlapping_readers(
+        &self,
+        resource: NodeIndex,
+        range: SubresourceRange,
+    ) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.graph
+            .edges_directed(resource, Direction::Outgoing)
+            .filter(move |edge| {
+                edge.target() != self.external_after_node
+                    && self.edge_range(edge.id(), Res(resource)).overlaps(&range)
+            })
+            .map(|edge| edge.target())
+    }
+
+    /// The passes writing to `resource`.
+    pub(crate) fn writers(&self, resource: NodeIndex) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.graph
+            .edges_directed(resource, Direction::Incoming)
+            .map(|edge| edge.source())
+            .filter(|&writer| writer != self.external_before_node)
+    }
+
+    /// Kahn's algorithm, always picking the earliest declared pass that is ready.
+    fn sort_passes(&self, passes: &HashSet<NodeIndex>) -> Result<Vec<NodeIndex>> {
+        let mut dependencies = HashMap::new();
+        let mut dependents = HashMap::<_, Vec<_>>::new();
+        for &pass in passes {
+            let deps = self.pass_dependencies(pass);
+            for &dep in &deps {
+                dependents.entry(dep).or_default().push(pass);
+            }
+            dependencies.insert(pass, deps);
+        }
+
+        let mut in_degree = dependencies
+            .iter()
+            .map(|(&pass, deps)| (pass, deps.len()))
+            .collect::<HashMap<_, _>>();
+
+        let mut ready = in_degree
+            .iter()
+            .filter(|(_, &degree)| degree == 0)
+            .map(|(&pass, _)| Reverse(pass))
+            .collect::<BinaryHeap<_>>();
+
+        let mut order = Vec::with_capacity(in_degree.len());
+        while let Some(Reverse(pass)) = ready.pop() {
+            order.push(pass);
+            for &next in dependents.get(&pass).into_iter().flatten() {
+                let degree = in_degree.get_mut(&next).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push(Reverse(next));
+                }
+            }
+        }
+
+        if order.len() < in_degree.len() {
+            // Every pass left has an unscheduled dependency, so walking back through them
+            // long enough is guaranteed to end up inside a cycle
+            let mut pass = *in_degree
+                .iter()
+                .filter(|(_, &degree)| degree > 0)
+                .map(|(pass, _)| pass)
+                .min()
+                .unwrap();
+            for _ in 0..in_degree.len() {
+                pass = *dependencies[&pass]
+                    .iter()
+                    .find(|dep| in_degree[dep] > 0)
+                    .unwrap();
+            }
+
+            return Err(TaskGraphError::Cycle(Action(pass)));
+        }
+
+        Ok(order)
+    }
+
+    fn compile_pass(&self, pass: NodeIndex, queue: QueueType) -> CompiledPass {
+        let mut reads = self
+            .graph
+            .edges_directed(pass, Direction::Incoming)
+            .filter_map(|edge| match *edge.weight() {
+                EdgeAction::Read(flags) => Some((
+                    edge.id(),
+                    PassRead {
+                        resource: Res(edge.source()),
+                        range: self.edge_range(edge.id(), Res(edge.source())),
+                        flags,
+                    },
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        reads.sort_by_key(|(edge, _)| *edge);
+
+        let mut writes = self
+            .graph
+            .edges_directed(pass, Direction::Outgoing)
+            .filter_map(|edge| match *edge.weight() {
+                EdgeAction::Write(flags, preserve) => Some((
+                    edge.id(),
+                    PassWrite {
+                        resource: Res(edge.target()),
+                        range: self.edge_range(edge.id(), Res(edge.target())),
+                        flags,
+                        preserve,
+                    },
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        writes.sort_by_key(|(edge, _)| *edge);
+
+        CompiledPass {
+            action: Action(pass),
+            kind: self.pass_kind(pass),
+            queue,
+            reads: reads.into_iter().map(|(_, read)| read).collect(),
+            writes: writes.into_iter().map(|(_, write)| write).collect(),
+            barriers: Vec::new(),
+        }
+    }
+
+    /// Transition every resource of the pass to the state the pass uses it in.
+    ///
+    /// The images in `general` are shared with other passes of its render group.
+    fn pass_barriers(
+        &self,
+        tracker: &mut BarrierTracker,
+        pass: &CompiledPass,
+        index: usize,
+        general: Option<&HashSet<Res>>,
+    ) -> Vec<Barrier> {
+        let at = SchedulePoint {
+            queue: pass.queue,
+            pass: index,
+        };
+
+        self.pass_accesses(pass)
+            .into_iter()
+            .flat_map(|access| {
+                let layout = match general {
+                    Some(general) if general.contains(&access.resource) => ImageLayout::General,
+                    _ => access.layout,
+                };
+                tracker.transition(
+                    access.resource,
+                    access.range,
+                    access.accesses,
+                    layout,
+                    access.discard,
+                    at,
+                )
+            })
+            .collect()
+    }
+
+    /// Merge every access of the pass to the same range of a resource, in order of first use.
+    pub(crate) fn pass_accesses(&self, pass: &CompiledPass) -> Vec<ResourceAccess> {
+        let mut usages = Vec::<ResourceUsage>::new();
+        for read in &pass.reads {
+            let usage = ResourceUsage::of(&mut usages, read.resource, read.range);
+            *usage.read.get_or_insert(ReadActionFlags::empty()) |= read.flags;
+        }
+        for write in &pass.writes {
+            let usage = ResourceUsage::of(&mut usages, write.resource, write.range);
+            *usage.write.get_or_insert(WriteActionFlags::empty()) |= write.flags;
+            usage.preserve |= write.preserve;
+        }
+
+        usages
+            .into_iter()
+            .map(|usage| {
+                let read = usage.read.map(|flags| self.effective_read_flags(flags));
+                let read_flags = read.unwrap_or_else(ReadActionFlags::empty);
+                let write_flags = usage.write.unwrap_or_else(WriteActionFlags::empty);
+
+                let mut accesses = read
+                    .map(|flags| read_accesses(pass.kind, flags))
+                    .unwrap_or_default();
+                accesses.extend(write_accesses(pass.kind, write_flags));
+
+                let layout = if self.is_image(usage.resource) {
+                    image_layout(read_flags, write_flags)
+                } else {
+                    ImageLayout::Optimal
+                };
+
+                ResourceAccess {
+                    resource: usage.resource,
+                    range: usage.range,
+                    accesses,
+                    layout,
+                    discard: usage.read.is_none() && !usage.preserve,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The state a pass needs a resource in.
+pub(crate) struct ResourceAccess {
+    pub(crate) resource: Res,
+    pub(crate) range: SubresourceRange,
+    pub(crate) accesses: Vec<AccessType>,
+    pub(crate) layout: ImageLayout,
+    /// Whether the pass doesn't care about the previous content
+    pub(crate) discard: bool,
+}
+
+/// Everything a single pass does with one range of a resource.
+struct ResourceUsage {
+    resource: Res,
+    range: SubresourceRange,
+    read: Option<ReadActionFlags>,
+    write: Option<WriteActionFlags>,
+    preserve: bool,
+}
+
+impl ResourceUsage {
+    fn of(
+        usages: &mut Vec<ResourceUsage>,
+        resource: Res,
+        range: SubresourceRange,
+    ) -> &mut ResourceUsage {
+        let i = match usages
+            .iter()
+            .position(|u| u.resource == resource && u.range == range)
+        {
+            Some(i) => i,
+            None => {
+                usages.push(ResourceUsage {
+                    resource,
+                    range,
+                    read: None,
+                    write: None,
+                    preserve: false,
+                });
+                usages.len() - 1
+            }
+        };
+        &mut usages[i]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        errors::TaskGraphError,
+        tests::{compile, Graph, IMAGE},
+    };
+
+    #[test]
+    fn schedule_follows_dependencies() {
+        let mut graph = Graph::new();
+
+        let gbuffer = graph.register_resource_image("GBuffer", IMAGE);
+        let backbuffer = graph.register_resource_image("Backbuffer", IMAGE);
+        let other = graph.register_resource_buffer("Other", 1024);
+
+        // Declared first but needs the geometry pass output
+        graph
+            .create_raster_pass("Lighting")
+            .add_sampled(gbuffer)
+            .add_color_attachment(backbuffer);
+        graph
+            .create_raster_pass("Geometry")
+            .add_color_attachment(gbuffer);
+        graph
+            .create_compute_pass("Unrelated")
+            .add_output_storage_buffer(other);
+        graph.copy_to_host(backbuffer);
+        graph.copy_to_host(other);
+
+        let compiled = compile(&graph);
+        let order = compiled
+            .passes()
+            .iter()
+            .map(|pass| *graph.pass_tag(pass.action))
+            .collect::<Vec<_>>();
+
+        assert_eq!(order, ["Geometry", "Lighting", "Unrelated"]);
+
+        let lighting = &compiled.passes()[1];
+        assert_eq!(lighting.reads.len(), 1);
+        assert_eq!(lighting.reads[0].resource, gbuffer);
+        assert_eq!(lighting.writes.len(), 1);
+        assert_eq!(lighting.writes[0].resource, backbuffer);
+    }
+
+    #[test]
+    fn preserving_write_runs_after_previous_writers() {
+        let mut graph = Graph::new();
+
+        let depth = graph.register_resource_image("Depth", IMAGE);
+        let color = graph.register_resource_image("Color", IMAGE);
+
+        let prepass = graph
+            .create_raster_pass("Prepass")
+            .add_depth_attachment(depth, false)
+            .id();
+        let main = graph
+            .create_raster_pass("Main")
+            .add_depth_attachment(depth, true)
+            .add_color_attachment(color)
+            .id();
+        graph.copy_to_host(color);
+
+        let compiled = compile(&graph);
+        let order = compiled
+            .passes()
+            .iter()
+            .map(|p| p.action)
+            .collect::<Vec<_>>();
+        assert_eq!(order, [prepass, main]);
+    }
+
+    #[test]
+    fn cycle_is_an_error() {
+        let mut graph = Graph::new();
+
+        let a = graph.register_resource_buffer("A", 1024);
+        let b = graph.register_resource_buffer("B", 1024);
+
+        let first = graph
+            .create_compute_pass("First")
+            .add_input_storage_buffer(a)
+            .add_output_storage_buffer(b)
+            .id();
+        let second = graph
+            .create_compute_pass("Second")
+            .add_input_storage_buffer(b)
+            .add_output_storage_buffer(a)
+            .id();
+        graph.copy_to_host(a);
+
+        match graph.compile() {
+            Err(TaskGraphError::Cycle(pass)) => assert!(pass == first || pass == second),
+            other => panic!("Expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unordered_overwrites_are_an_error() {
+        let mut graph = Graph::new();
+
+        let color = graph.register_resource_image("Color", IMAGE);
+
+        let first = graph
+            .create_raster_pass("First")
+            .add_color_attachment(color)
+            .id();
+        let second = graph
+            .create_raster_pass("Second")
+            .add_color_attachment(color)
+            .id();
+        graph.copy_to_host(color);
+
+        assert_eq!(
+            graph.compile().unwrap_err(),
+            TaskGraphError::UnorderedWrites(first, second, color)
+        );
+    }
+}
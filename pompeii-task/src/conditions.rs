@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use petgraph::{
+    prelude::{DiGraph, NodeIndex},
+    visit::EdgeRef,
+    Direction,
+};
+
+use crate::{Action, ActionTy, EdgeAction, NodeId, Res, ResourceTy, TaskGraph};
+
+/// Decides whether a pass runs, checked every time the graph is compiled.
+pub type ConditionFn = Box<dyn Fn() -> bool + Send + Sync>;
+
+/// The conditions of the passes that can be turned off.
+#[derive(Default)]
+pub(crate) struct Conditions {
+    pub(crate) predicates: HashMap<NodeIndex, ConditionFn>,
+    /// `(input, output)` pairs, the input being used in place of the output when the pass is
+    /// disabled
+    pub(crate) passthroughs: HashMap<NodeIndex, Vec<(NodeIndex, NodeIndex)>>,
+}
+
+/// The passes left out of a compilation, sorted so they can be hashed.
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Disabled {
+    pub(crate) passes: BTreeSet<NodeIndex>,
+    /// Outputs of disabled passthrough passes, along with the resource used in their place
+    pub(crate) forwards: BTreeMap<NodeIndex, NodeIndex>,
+}
+
+impl<R, C> TaskGraph<R, C> {
+    /// Turn a pass on or off, replacing its condition.
+    pub fn set_enabled(&mut self, Action(pass): Action, enabled: bool) {
+        self.conditions
+            .predicates
+            .insert(pass, Box::new(move || enabled));
+    }
+
+    /// Whether the condition of a pass holds, passes without one always run.
+    ///
+    /// An enabled pass can still be left out if it reads what a disabled pass writes.
+    pub fn is_enabled(&self, Action(pass): Action) -> bool {
+        self.conditions
+            .predicates
+            .get(&pass)
+            .map(|condition| condition())
+            .unwrap_or(true)
+    }
+
+    /// The passes turned off by their condition, along with the ones reading what they write
+    /// when it isn't forwarded.
+    pub(crate) fn disabled(&self) -> Disabled {
+        let mut passes = self
+            .pass_nodes()
+            .filter(|&pass| !self.is_enabled(Action(pass)))
+            .collect::<BTreeSet<_>>();
+        if passes.is_empty() {
+            return Disabled::default();
+        }
+
+        loop {
+            let forwards = self.forwards(&passes);
+            let dependents = self
+                .pass_nodes()
+                .filter(|pass| !passes.contains(pass))
+                .filter(|&pass| {
+                    self.content_dependencies(pass)
+                        .any(|res| !self.is_available(res, &passes, &forwards))
+                })
+                .collect::<Vec<_>>();
+
+            if dependents.is_empty() {
+                let forwards = forwards
+                    .keys()
+                    .map(|&output| (output, resolve(output, &forwards)))
+                    .collect();
+                return Disabled { passes, forwards };
+            }
+            passes.extend(dependents);
+        }
+    }
+
+    /// Outputs of the disabled passes forwarded to their input, along with the pass doing it.
+    ///
+    /// Imported and history resources live outside of the graph and can't be replaced.
+    fn forwards(
+        &self,
+        disabled: &BTreeSet<NodeIndex>,
+    ) -> BTreeMap<NodeIndex, (NodeIndex, NodeIndex)> {
+        disabled
+            .iter()
+            .flat_map(|&pass| {
+                self.conditions
+                    .passthroughs
+                    .get(&pass)
+                    .into_iter()
+                    .flatten()
+                    .map(move |&(input, output)| (output, (pass, input)))
+            })
+            .filter(|&(output, _)| !self.is_imported(Res(output)) && !self.is_history(Res(output)))
+            .collect()
+    }
+
+    /// The resources whose content the pass uses: the ones it reads or writes on top of.
+    fn content_dependencies(&self, pass: NodeIndex) -> impl Iterator<Item = NodeIndex> + '_ {
+        let reads = self
+            .graph
+            .edges_directed(pass, Direction::Incoming)
+            .map(|edge| edge.source());
+        let preserved = self
+            .graph
+            .edges_directed(pass, Direction::Outgoing)
+            .filter(|edge| matches!(edge.weight(), EdgeAction::Write(_, true)))
+            .map(|edge| edge.target());
+        reads.chain(preserved)
+    }
+
+    /// Whether no disabled pass writes the resource, or what it is forwarded to.
+    fn is_available(
+        &self,
+        mut res: NodeIndex,
+        disabled: &BTreeSet<NodeIndex>,
+        forwards: &BTreeMap<NodeIndex, (NodeIndex, NodeIndex)>,
+    ) -> bool {
+        for _ in 0..=forwards.len() {
+            let forwarded = forwards.get(&res).copied();
+            if self.writers(res).any(|writer| {
+                disabled.contains(&writer) && forwarded.map(|(pass, _)| pass) != Some(writer)
+            }) {
+                return false;
+            }
+
+            match forwarded {
+                Some((_, input)) => res = input,
+                None => return true,
+            }
+        }
+
+        // Passthroughs forwarding to each other in a loop
+        false
+    }
+
+    /// The graph the passes left run in, every access to a forwarded resource going to the
+    /// resource used in its place. Nodes keep their index, tags and record callbacks are left
+    /// out.
+    pub(crate) fn without_disabled(&self, disabled: &Disabled) -> TaskGraph<(), ()> {
+        let mut graph = DiGraph::with_capacity(self.graph.node_count(), self.graph.edge_count());
+        for node in self.graph.node_weights() {
+            graph.add_node(match node {
+                NodeId::Resource(ResourceTy::Buffer(_, size)) => {
+                    NodeId::Resource(ResourceTy::Buffer((), *size))
+                }
+                NodeId::Resource(ResourceTy::Image(_, desc)) => {
+                    NodeId::Resource(ResourceTy::Image((), *desc))
+                }
+                NodeId::Action(action) => NodeId::Action(untagged(action)),
+            });
+        }
+
+        let forward = |node| disabled.forwards.get(&node).copied().unwrap_or(node);
+        let mut ranges = HashMap::new();
+        for edge in self.graph.edge_references() {
+            if disabled.passes.contains(&edge.source()) || disabled.passes.contains(&edge.target())
+            {
+                continue;
+            }
+
+            let id = graph.add_edge(
+                forward(edge.source()),
+                forward(edge.target()),
+                edge.weight().clone(),
+            );
+            if let Some(&range) = self.ranges.get(&edge.id()) {
+                ranges.insert(id, range);
+            }
+        }
+
+        TaskGraph {
+            graph,
+            external_before_node: self.external_before_node,
+            external_after_node: self.external_after_node,
+            imports: self.imports.clone(),
+            histories: self.histories.clone(),
+            ranges,
+            records: HashMap::new(),
+            conditions: Conditions::default(),
+            local_read: self.local_read,
+        }
+    }
+}
+
+/// Follow forwarded resources until one that isn't.
+fn resolve(
+    mut res: NodeIndex,
+    forwards: &BTreeMap<NodeIndex, (NodeIndex, NodeIndex)>,
+) -> NodeIndex {
+    for _ in 0..forwards.len() {
+        match forwards.get(&res) {
+            Some(&(_, input)) => res = input,
+            None => break,
+        }
+    }
+    res
+}
+
+fn untagged<C>(action: &ActionTy<C>) -> ActionTy<()> {
+    match action {
+        ActionTy::External => ActionTy::External,
+        ActionTy::Raster(_) => ActionTy::Raster(()),
+        ActionTy::Compute(_) => ActionTy::Compute(()),
+        ActionTy::RayTracing(_) => ActionTy::RayTracing(()),
+        ActionTy::Transfer(_) => ActionTy::Transfer(()),
+        ActionTy::AccelerationStructureBuild(_) => ActionTy::AccelerationStructureBuild(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use crate::tests::{compile, Graph, HDR_IMAGE};
+
+    #[test]
+    fn disabled_passthrough_forwards_its_input() {
+        let mut graph = Graph::new();
+
+        let color = graph.register_resource_image("Color", HDR_IMAGE);
+        let denoised = graph.register_resource_image("Denoised", HDR_IMAGE);
+        let tonemapped = graph.register_resource_image("Tonemapped", HDR_IMAGE);
+
+        let denoise = Arc::new(AtomicBool::new(true));
+        let enabled = denoise.clone();
+
+        graph
+            .create_raster_pass("Scene")
+            .add_color_attachment(color);
+        let denoiser = graph
+            .create_raster_pass("Denoise")
+            .add_sampled(color)
+            .add_color_attachment(denoised)
+            .enable_if(move || enabled.load(Ordering::Relaxed))
+            .passthrough(color, denoised)
+            .id();
+        let tonemap = graph
+            .create_raster_pass("Tonemap")
+            .add_sampled(denoised)
+            .add_color_attachment(tonemapped)
+            .id();
+        graph.copy_to_host(tonemapped);
+
+        let enabled_hash = graph.structural_hash();
+        assert_eq!(compile(&graph).passes().len(), 3);
+
+        denoise.store(false, Ordering::Relaxed);
+        assert_ne!(graph.structural_hash(), enabled_hash);
+
+        let compiled = compile(&graph);
+        assert_eq!(compiled.culled_passes(), [denoiser]);
+        assert_eq!(compiled.forwarded_resources(), [(denoised, color)]);
+
+        let tonemap = compiled
+            .passes()
+            .iter()
+            .find(|pass| pass.action == tonemap)
+            .unwrap();
+        assert_eq!(tonemap.reads[0].resource, color);
+        assert!(tonemap.barriers.iter().all(|b| b.resource != denoised));
+
+        denoise.store(true, Ordering::Relaxed);
+        assert_eq!(graph.structural_hash(), enabled_hash);
+    }
+
+    #[test]
+    fn dependents_of_disabled_passes_are_culled() {
+        let mut graph = Graph::new();
+
+        let color = graph.register_resource_image("Color", HDR_IMAGE);
+        let occlusion = graph.register_resource_image("Occlusion", HDR_IMAGE);
+        let blurred = graph.register_resource_image("Blurred occlusion", HDR_IMAGE);
+
+        graph
+            .create_raster_pass("Scene")
+            .add_color_attachment(color);
+        let ssao = graph
+            .create_raster_pass("SSAO")
+            .add_sampled(color)
+            .add_color_attachment(occlusion)
+            .id();
+        let blur = graph
+            .create_raster_pass("Blur")
+            .add_sampled(occlusion)
+            .add_color_attachment(blurred)
+            .id();
+        let composite = graph
+            .create_transfer_pass("Composite")
+            .add_source(blurred)
+            .add_destination(color, true)
+            .id();
+        graph.copy_to_host(color);
+
+        graph.set_enabled(ssao, false);
+        assert!(!graph.is_enabled(ssao) && graph.is_enabled(blur));
+
+        let compiled = compile(&graph);
+        assert_eq!(compiled.passes().len(), 1);
+        assert_eq!(compiled.culled_passes(), [ssao, blur, composite]);
+        assert!(compiled.forwarded_resources().is_empty());
+    }
+}
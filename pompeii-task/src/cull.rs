@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+
+use petgraph::{prelude::NodeIndex, visit::EdgeRef, Direction};
+
+use crate::TaskGraph;
+
+impl<R, C> TaskGraph<R, C> {
+    /// The passes contributing to something that leaves the graph, found by walking the
+    /// dependencies back from the resources read by the host, imported or kept for the next
+    /// frame.
+    pub(crate) fn live_passes(&self) -> HashSet<NodeIndex> {
+        let mut to_visit = self
+            .graph
+            .edges_directed(self.external_after_node, Direction::Incoming)
+            .map(|edge| edge.source())
+            .chain(self.imports.keys().copied())
+            .chain(self.histories.iter().map(|history| history.current.0))
+            .flat_map(|resource| self.writers(resource))
+            .collect::<Vec<_>>();
+
+        let mut live = HashSet::new();
+        while let Some(pass) = to_visit.pop() {
+            if live.insert(pass) {
+                to_visit.extend(self.pass_dependencies(pass));
+            }
+        }
+
+        live
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::tests::{compile, Graph, IMAGE};
+
+    #[test]
+    fn unused_passes_are_culled() {
+        let mut graph = Graph::new();
+
+        let color = graph.register_resource_image("Color", IMAGE);
+        let debug = graph.register_resource_image("Debug", IMAGE);
+        let debug_overlay = graph.register_resource_image("Debug overlay", IMAGE);
+
+        let main = graph
+            .create_raster_pass("Main")
+            .add_color_attachment(color)
+            .id();
+        let debug_pass = graph
+            .create_raster_pass("Debug")
+            .add_color_attachment(debug)
+            .id();
+        let overlay = graph
+            .create_raster_pass("Debug overlay")
+            .add_sampled(debug)
+            .add_color_attachment(debug_overlay)
+            .id();
+        graph.copy_to_host(color);
+
+        let compiled = compile(&graph);
+
+        assert_eq!(compiled.passes().len(), 1);
+        assert_eq!(compiled.passes()[0].action, main);
+        assert_eq!(compiled.culled_passes(), [debug_pass, overlay]);
+    }
+
+    #[test]
+    fn dependencies_of_live_passes_are_kept() {
+        let mut graph = Graph::new();
+
+        let depth = graph.register_resource_image("Depth", IMAGE);
+        let color = graph.register_resource_image("Color", IMAGE);
+
+        graph
+            .create_raster_pass("Prepass")
+            .add_depth_attachment(depth, false);
+        graph
+            .create_raster_pass("Main")
+            .add_depth_attachment(depth, true)
+            .add_color_attachment(color);
+        graph.copy_to_host(color);
+
+        let compiled = compile(&graph);
+
+        assert_eq!(compiled.passes().len(), 2);
+        assert!(compiled.culled_passes().is_empty());
+    }
+}
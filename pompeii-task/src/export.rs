@@ -0,0 +1,712 @@
+use std::{
+    collections::HashSet,
+    fmt::{Display, Write},
+};
+
+use petgraph::{
+    prelude::{EdgeIndex, NodeIndex},
+    visit::EdgeRef,
+};
+
+use crate::{
+    Action, Barrier, CompiledGraph, EdgeAction, ExternalState, NodeId, ReadActionFlags, Res,
+    ResourceTy, SubresourceRange, TaskGraph, WriteActionFlags,
+};
+
+// Both the DOT and JSON outputs only depend on the declaration order of the graph, so they can
+// be diffed between runs.
+
+impl<R: Display, C: Display> TaskGraph<R, C> {
+    /// The graph in Graphviz format, culled passes are dashed.
+    pub fn to_dot(&self) -> String {
+        let live = self.running_passes();
+        let mut dot = String::from("digraph {\n    rankdir=LR\n");
+
+        dot.push_str("    host_in [label=\"Host\" shape=diamond]\n");
+        dot.push_str("    host_out [label=\"Host\" shape=diamond]\n");
+
+        for pass in self.pass_nodes() {
+            let style = if live.contains(&pass) {
+                ""
+            } else {
+                " style=dashed"
+            };
+            writeln!(
+                dot,
+                "    n{} [label=\"{}\\n{:?}\" shape=box{}]",
+                pass.index(),
+                escape(&self.node_name(pass)),
+                self.pass_kind(pass),
+                style
+            )
+            .unwrap();
+        }
+
+        for resource in self.resource_nodes() {
+            writeln!(
+                dot,
+                "    n{} [label=\"{}\\n{}\" shape=ellipse]",
+                resource.index(),
+                escape(&self.node_name(resource)),
+                self.resource_summary(resource)
+            )
+            .unwrap();
+        }
+
+        for (from, to, label) in self.export_edges() {
+            writeln!(
+                dot,
+                "    {} -> {} [label=\"{}\"]",
+                dot_node(self, from, "host_in"),
+                dot_node(self, to, "host_out"),
+                label
+            )
+            .unwrap();
+        }
+
+        for (resource, import) in self.sorted_imports() {
+            writeln!(
+                dot,
+                "    host_in -> n{} [label=\"{}\" style=dotted]",
+                resource.0.index(),
+                state_summary(&import.current)
+            )
+            .unwrap();
+            writeln!(
+                dot,
+                "    n{} -> host_out [label=\"{}\" style=dotted]",
+                resource.0.index(),
+                state_summary(&import.final_state)
+            )
+            .unwrap();
+        }
+
+        for history in &self.histories {
+            writeln!(
+                dot,
+                "    n{} -> n{} [label=\"next frame\" style=dotted]",
+                history.current.0.index(),
+                history.previous.0.index()
+            )
+            .unwrap();
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The graph as a JSON object with its `passes`, `resources` and `edges`.
+    ///
+    /// Edges from and to the host have a `null` pass.
+    pub fn to_json(&self) -> String {
+        let live = self.running_passes();
+
+        let passes = self
+            .pass_nodes()
+            .map(|pass| {
+                format!(
+                    "{{\"id\":{},\"name\":\"{}\",\"kind\":\"{:?}\",\"culled\":{}}}",
+                    pass.index(),
+                    escape(&self.node_name(pass)),
+                    self.pass_kind(pass),
+                    !live.contains(&pass)
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let resources = self
+            .resource_nodes()
+            .map(|resource| {
+                let desc = match &self.graph[resource] {
+                    NodeId::Resource(ResourceTy::Buffer(_, size)) => {
+                        format!("\"type\":\"buffer\",\"size\":{}", size)
+                    }
+                    NodeId::Resource(ResourceTy::Image(_, desc)) => format!(
+                        "\"type\":\"image\",\"extent\":[{},{},{}],\"format\":\"{:?}\",\
+                         \"mip_levels\":{},\"array_layers\":{}",
+                        desc.extent.width,
+                        desc.extent.height,
+                        desc.extent.depth,
+                        desc.format,
+                        desc.mip_levels,
+                        desc.array_layers
+                    ),
+                    _ => unreachable!("Not a resource"),
+                };
+                let import = match self.imports.get(&resource) {
+                    Some(import) => format!(
+                        "{{\"current\":{},\"final\":{}}}",
+                        state_json(&import.current),
+                        state_json(&import.final_state)
+                    ),
+                    None => "null".to_string(),
+                };
+                format!(
+                    "{{\"id\":{},\"name\":\"{}\",{},\"import\":{}}}",
+                    resource.index(),
+                    escape(&self.node_name(resource)),
+                    desc,
+                    import
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let edges = self
+            .sorted_edges()
+            .into_iter()
+            .map(|(edge, source, target, action)| {
+                let range = match self.ranges.get(&edge) {
+                    Some(range) => range_json(range),
+                    None => "null".to_string(),
+                };
+                let pass_id = |node: NodeIndex| {
+                    if self.is_external(node) {
+                        "null".to_string()
+                    } else {
+                        node.index().to_string()
+                    }
+                };
+
+                match *action {
+                    EdgeAction::Read(flags) => format!(
+                        "{{\"access\":\"read\",\"pass\":{},\"resource\":{},\"flags\":{},\
+                         \"range\":{}}}",
+                        pass_id(target),
+                        source.index(),
+                        json_strings(read_flag_names(flags)),
+                        range
+                    ),
+                    EdgeAction::Write(flags, preserve) => format!(
+                        "{{\"access\":\"write\",\"pass\":{},\"resource\":{},\"flags\":{},\
+                         \"preserve\":{},\"range\":{}}}",
+                        pass_id(source),
+                        target.index(),
+                        json_strings(write_flag_names(flags)),
+                        preserve,
+                        range
+                    ),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let histories = self
+            .histories
+            .iter()
+            .map(|history| {
+                format!(
+                    "{{\"current\":{},\"previous\":{}}}",
+                    history.current.0.index(),
+                    history.previous.0.index()
+                )
+            })
+            .collect::<Vec<_>>();
+
+        format!(
+            "{{\"passes\":[{}],\"resources\":[{}],\"edges\":[{}],\"histories\":[{}]}}",
+            passes.join(","),
+            resources.join(","),
+            edges.join(","),
+            histories.join(",")
+        )
+    }
+
+    fn node_name(&self, node: NodeIndex) -> String {
+        let name = match &self.graph[node] {
+            NodeId::Resource(ResourceTy::Buffer(tag, _) | ResourceTy::Image(tag, _)) => {
+                tag.to_string()
+            }
+            NodeId::Action(_) => self.pass_tag(Action(node)).to_string(),
+        };
+
+        // Both halves of a history share their tag
+        if self.is_history_previous(Res(node)) {
+            format!("{} (previous)", name)
+        } else {
+            name
+        }
+    }
+
+    fn resource_summary(&self, resource: NodeIndex) -> String {
+        match &self.graph[resource] {
+            NodeId::Resource(ResourceTy::Buffer(_, size)) => format!("Buffer {} bytes", size),
+            NodeId::Resource(ResourceTy::Image(_, desc)) => format!(
+                "Image {}x{}x{} {:?}",
+                desc.extent.width, desc.extent.height, desc.extent.depth, desc.format
+            ),
+            _ => unreachable!("Not a resource"),
+        }
+    }
+
+    /// Every edge as `(source, target, label)`, in declaration order.
+    fn export_edges(&self) -> Vec<(NodeIndex, NodeIndex, String)> {
+        self.sorted_edges()
+            .into_iter()
+            .map(|(edge, source, target, action)| {
+                let mut label = match *action {
+                    EdgeAction::Read(flags) => read_flag_names(flags).join(" | "),
+                    EdgeAction::Write(flags, preserve) => {
+                        let mut names = write_flag_names(flags);
+                        if preserve {
+                            names.push("preserve");
+                        }
+                        names.join(" | ")
+                    }
+                };
+                if let Some(range) = self.ranges.get(&edge) {
+                    write!(label, "\\n{}", range_summary(range)).unwrap();
+                }
+                (source, target, label)
+            })
+            .collect()
+    }
+}
+
+impl<R, C> TaskGraph<R, C> {
+    fn resource_nodes(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.graph
+            .node_indices()
+            .filter(|&node| matches!(self.graph[node], NodeId::Resource(_)))
+    }
+
+    fn sorted_edges(&self) -> Vec<(EdgeIndex, NodeIndex, NodeIndex, &EdgeAction)> {
+        let mut edges = self.graph.edge_references().collect::<Vec<_>>();
+        edges.sort_by_key(|edge| edge.id());
+        edges
+            .into_iter()
+            .map(|edge| (edge.id(), edge.source(), edge.target(), edge.weight()))
+            .collect()
+    }
+
+    /// The passes a compilation would keep, the disabled ones being culled too.
+    fn running_passes(&self) -> HashSet<NodeIndex> {
+        let disabled = self.disabled();
+        if disabled.passes.is_empty() {
+            self.live_passes()
+        } else {
+            self.without_disabled(&disabled).live_passes()
+        }
+    }
+
+    fn is_external(&self, node: NodeIndex) -> bool {
+        node == self.external_before_node || node == self.external_after_node
+    }
+}
+
+impl CompiledGraph {
+    /// The schedule in Graphviz format, one cluster per submission with the barriers recorded
+    /// before each pass. `graph` is the one this was compiled from, for the names.
+    pub fn to_dot<R: Display, C: Display>(&self, graph: &TaskGraph<R, C>) -> String {
+        let mut dot = String::from("digraph {\n    rankdir=TB\n    node [shape=box]\n");
+
+        for (i, submission) in self.submissions().iter().enumerate() {
+            writeln!(dot, "    subgraph cluster_{} {{", i).unwrap();
+            writeln!(
+                dot,
+                "        label=\"Submission {} ({:?})\"",
+                i, submission.queue
+            )
+            .unwrap();
+
+            for index in submission.passes.clone() {
+                let pass = &self.passes()[index];
+                let mut label = format!(
+                    "{}\\n{:?}",
+                    escape(&graph.node_name(pass.action.0)),
+                    pass.kind
+                );
+                for barrier in &pass.barriers {
+                    write!(label, "\\l{}", escape(&barrier_summary(graph, barrier))).unwrap();
+                }
+                if !pass.barriers.is_empty() {
+                    label.push_str("\\l");
+                }
+
+                writeln!(dot, "        p{} [label=\"{}\"]", index, label).unwrap();
+            }
+            dot.push_str("    }\n");
+        }
+
+        let mut label = String::from("End");
+        for barrier in self.final_barriers() {
+            write!(label, "\\l{}", escape(&barrier_summary(graph, barrier))).unwrap();
+        }
+        if !self.final_barriers().is_empty() {
+            label.push_str("\\l");
+        }
+        writeln!(dot, "    end [label=\"{}\" shape=diamond]", label).unwrap();
+
+        for index in 0..self.passes().len() {
+            let next = if index + 1 < self.passes().len() {
+                format!("p{}", index + 1)
+            } else {
+                "end".to_string()
+            };
+            writeln!(dot, "    p{} -> {}", index, next).unwrap();
+        }
+
+        for submission in self.submissions() {
+            for &wait in &submission.waits {
+                writeln!(
+                    dot,
+                    "    p{} -> p{} [style=dashed label=\"wait\"]",
+                    self.submissions()[wait].passes.end - 1,
+                    submission.passes.start
+                )
+                .unwrap();
+            }
+        }
+
+        for &culled in self.culled_passes() {
+            writeln!(
+                dot,
+                "    culled{} [label=\"{}\" style=dashed]",
+                culled.0.index(),
+                escape(&graph.node_name(culled.0))
+            )
+            .unwrap();
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The schedule as a JSON object with its `passes`, `submissions`, `final_barriers` and
+    /// `culled` passes. `graph` is the one this was compiled from, for the names.
+    pub fn to_json<R: Display, C: Display>(&self, graph: &TaskGraph<R, C>) -> String {
+        let passes = self
+            .passes()
+            .iter()
+            .map(|pass| {
+                format!(
+                    "{{\"id\":{},\"name\":\"{}\",\"kind\":\"{:?}\",\"queue\":\"{:?}\",\
+                     \"barriers\":{}}}",
+                    pass.action.0.index(),
+                    escape(&graph.node_name(pass.action.0)),
+                    pass.kind,
+                    pass.queue,
+                    barriers_json(&pass.barriers)
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let submissions = self
+            .submissions()
+            .iter()
+            .map(|submission| {
+                format!(
+                    "{{\"queue\":\"{:?}\",\"passes\":[{},{}],\"waits\":[{}]}}",
+                    submission.queue,
+                    submission.passes.start,
+                    submission.passes.end,
+                    join(submission.waits.iter())
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let culled = self
+            .culled_passes()
+            .iter()
+            .map(|culled| {
+                format!(
+                    "{{\"id\":{},\"name\":\"{}\"}}",
+                    culled.0.index(),
+                    escape(&graph.node_name(culled.0))
+                )
+            })
+            .collect::<Vec<_>>();
+
+        format!(
+            "{{\"passes\":[{}],\"submissions\":[{}],\"final_barriers\":{},\"culled\":[{}]}}",
+            passes.join(","),
+            submissions.join(","),
+            barriers_json(self.final_barriers()),
+            culled.join(",")
+        )
+    }
+}
+
+fn dot_node<R, C>(graph: &TaskGraph<R, C>, node: NodeIndex, external: &str) -> String {
+    if graph.is_external(node) {
+        external.to_string()
+    } else {
+        format!("n{}", node.index())
+    }
+}
+
+fn barrier_summary<R: Display, C: Display>(graph: &TaskGraph<R, C>, barrier: &Barrier) -> String {
+    let mut summary = format!(
+        "{}: {:?} -> {:?}",
+        graph.node_name(barrier.resource.0),
+        barrier.previous_accesses,
+        barrier.next_accesses
+    );
+    if barrier.previous_layout != barrier.next_layout {
+        write!(
+            summary,
+            " ({:?} -> {:?})",
+            barrier.previous_layout, barrier.next_layout
+        )
+        .unwrap();
+    }
+    if barrier.range != graph.full_range(barrier.resource) {
+        write!(summary, " {}", range_summary(&barrier.range)).unwrap();
+    }
+    if let Some(transfer) = barrier.queue_transfer {
+        write!(summary, " [{:?} -> {:?}]", transfer.from, transfer.to).unwrap();
+    }
+    summary
+}
+
+fn range_summary(range: &SubresourceRange) -> String {
+    format!(
+        "mips {}..{} layers {}..{}",
+        range.base_mip_level,
+        range.base_mip_level + range.level_count,
+        range.base_array_layer,
+        range.base_array_layer + range.layer_count
+    )
+}
+
+fn range_json(range: &SubresourceRange) -> String {
+    format!(
+        "{{\"base_mip_level\":{},\"level_count\":{},\"base_array_layer\":{},\"layer_count\":{}}}",
+        range.base_mip_level, range.level_count, range.base_array_layer, range.layer_count
+    )
+}
+
+fn state_summary(state: &ExternalState) -> String {
+    format!("{:?} {:?}", state.accesses, state.layout)
+}
+
+fn state_json(state: &ExternalState) -> String {
+    format!(
+        "{{\"accesses\":{},\"layout\":\"{:?}\"}}",
+        json_strings(state.accesses.iter().map(|a| format!("{:?}", a))),
+        state.layout
+    )
+}
+
+fn barriers_json(barriers: &[Barrier]) -> String {
+    let barriers = barriers
+        .iter()
+        .map(|barrier| {
+            let transfer = match barrier.queue_transfer {
+                Some(transfer) => format!(
+                    "{{\"from\":\"{:?}\",\"to\":\"{:?}\",\"release_after\":{}}}",
+                    transfer.from, transfer.to, transfer.release_after
+                ),
+                None => "null".to_string(),
+            };
+
+            format!(
+                "{{\"resource\":{},\"range\":{},\"previous_accesses\":{},\"next_accesses\":{},\
+                 \"previous_layout\":\"{:?}\",\"next_layout\":\"{:?}\",\
+                 \"discard_contents\":{},\"queue_transfer\":{}}}",
+                barrier.resource.0.index(),
+                range_json(&barrier.range),
+                json_strings(barrier.previous_accesses.iter().map(|a| format!("{:?}", a))),
+                json_strings(barrier.next_accesses.iter().map(|a| format!("{:?}", a))),
+                barrier.previous_layout,
+                barrier.next_layout,
+                barrier.discard_contents,
+                transfer
+            )
+        })
+        .collect::<Vec<_>>();
+
+    format!("[{}]", barriers.join(","))
+}
+
+fn read_flag_names(flags: ReadActionFlags) -> Vec<&'static str> {
+    let names = [
+        (ReadActionFlags::INPUT_ATTACHMENT, "INPUT_ATTACHMENT"),
+        (ReadActionFlags::DEPTH_ATTACHMENT, "DEPTH_ATTACHMENT"),
+        (ReadActionFlags::SAMPLED, "SAMPLED"),
+        (ReadActionFlags::TRANSFER, "TRANSFER"),
+        (ReadActionFlags::STORAGE, "STORAGE"),
+        (
+            ReadActionFlags::ACCELERATION_STRUCTURE,
+            "ACCELERATION_STRUCTURE",
+        ),
+    ];
+
+    if flags.is_empty() {
+        return vec!["BOUND"];
+    }
+    names
+        .into_iter()
+        .filter(|(flag, _)| flags.contains(*flag))
+        .map(|(_, name)| name)
+        .collect()
+}
+
+fn write_flag_names(flags: WriteActionFlags) -> Vec<&'static str> {
+    let names = [
+        (WriteActionFlags::COLOR_ATTACHMENT, "COLOR_ATTACHMENT"),
+        (WriteActionFlags::DEPTH_ATTACHMENT, "DEPTH_ATTACHMENT"),
+        (WriteActionFlags::TRANSFER, "TRANSFER"),
+        (WriteActionFlags::STORAGE, "STORAGE"),
+        (
+            WriteActionFlags::ACCELERATION_STRUCTURE,
+            "ACCELERATION_STRUCTURE",
+        ),
+    ];
+
+    names
+        .into_iter()
+        .filter(|(flag, _)| flags.contains(*flag))
+        .map(|(_, name)| name)
+        .collect()
+}
+
+fn json_strings<S: AsRef<str>>(strings: impl IntoIterator<Item = S>) -> String {
+    let strings = strings
+        .into_iter()
+        .map(|s| format!("\"{}\"", escape(s.as_ref())))
+        .collect::<Vec<_>>();
+    format!("[{}]", strings.join(","))
+}
+
+fn join(values: impl Iterator<Item = impl ToString>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// Escape a string to put it between double quotes, valid for both DOT and JSON.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use vk_sync_fork::AccessType;
+
+    use crate::{
+        tests::{compile, Graph, IMAGE},
+        ExternalState,
+    };
+
+    fn graph() -> Graph {
+        let mut graph = Graph::new();
+
+        let texture = graph.register_resource_image("Texture", IMAGE);
+        let color = graph.register_resource_image("Color", IMAGE);
+        let unused = graph.register_resource_buffer("Unused", 64);
+
+        graph.copy_from_host(texture);
+        graph
+            .create_raster_pass("Main")
+            .add_sampled(texture)
+            .add_color_attachment(color);
+        graph
+            .create_compute_pass("Dead")
+            .add_output_storage_buffer(unused);
+        graph.copy_to_host(color);
+
+        graph
+    }
+
+    #[test]
+    fn graph_json() {
+        assert_eq!(
+            graph().to_json(),
+            concat!(
+                r#"{"passes":["#,
+                r#"{"id":5,"name":"Main","kind":"Raster","culled":false},"#,
+                r#"{"id":6,"name":"Dead","kind":"Compute","culled":true}],"#,
+                r#""resources":["#,
+                r#"{"id":2,"name":"Texture","type":"image","extent":[1920,1080,1],"#,
+                r#""format":"R8G8B8A8_UNORM","mip_levels":1,"array_layers":1,"import":null},"#,
+                r#"{"id":3,"name":"Color","type":"image","extent":[1920,1080,1],"#,
+                r#""format":"R8G8B8A8_UNORM","mip_levels":1,"array_layers":1,"import":null},"#,
+                r#"{"id":4,"name":"Unused","type":"buffer","size":64,"import":null}],"#,
+                r#""edges":["#,
+                r#"{"access":"write","pass":null,"resource":2,"flags":["TRANSFER"],"preserve":false,"range":null},"#,
+                r#"{"access":"read","pass":5,"resource":2,"flags":["SAMPLED"],"range":null},"#,
+                r#"{"access":"write","pass":5,"resource":3,"flags":["COLOR_ATTACHMENT"],"preserve":false,"range":null},"#,
+                r#"{"access":"write","pass":6,"resource":4,"flags":["STORAGE"],"preserve":false,"range":null},"#,
+                r#"{"access":"read","pass":null,"resource":3,"flags":["TRANSFER"],"range":null}],"#,
+                r#""histories":[]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn graph_dot() {
+        let dot = graph().to_dot();
+
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains("n5 [label=\"Main\\nRaster\" shape=box]"));
+        assert!(dot.contains("n6 [label=\"Dead\\nCompute\" shape=box style=dashed]"));
+        assert!(dot.contains("host_in -> n2 [label=\"TRANSFER\"]"));
+        assert!(dot.contains("n3 -> host_out [label=\"TRANSFER\"]"));
+    }
+
+    #[test]
+    fn compiled_exports() {
+        let graph = graph();
+        let compiled = compile(&graph);
+
+        let json = compiled.to_json(&graph);
+        assert!(json.starts_with(
+            r#"{"passes":[{"id":5,"name":"Main","kind":"Raster","queue":"Graphics","barriers":["#
+        ));
+        assert!(json.contains(r#""submissions":[{"queue":"Graphics","passes":[0,1],"waits":[]}]"#));
+        assert!(json.ends_with(r#""culled":[{"id":6,"name":"Dead"}]}"#));
+
+        let dot = compiled.to_dot(&graph);
+        assert!(dot.contains("label=\"Submission 0 (Graphics)\""));
+        assert!(dot.contains("Color: [Nothing] -> [ColorAttachmentWrite]"));
+        assert!(dot.contains("culled6 [label=\"Dead\" style=dashed]"));
+        assert_eq!(dot, compiled.to_dot(&graph));
+    }
+
+    #[test]
+    fn names_and_disabled_passes() {
+        let mut graph = Graph::new();
+        let color = graph.register_resource_image("Say \"hi\"", IMAGE);
+        graph
+            .create_raster_pass("Off")
+            .add_color_attachment(color)
+            .enable_if(|| false);
+        graph.copy_to_host(color);
+
+        let json = graph.to_json();
+        assert!(json.contains(r#""name":"Say \"hi\"""#));
+        assert!(json.contains(r#"{"id":3,"name":"Off","kind":"Raster","culled":true}"#));
+        assert!(graph
+            .to_dot()
+            .contains("n3 [label=\"Off\\nRaster\" shape=box style=dashed]"));
+    }
+
+    #[test]
+    fn imported_states() {
+        let mut graph = Graph::new();
+        let backbuffer = graph.import_image(
+            "Backbuffer",
+            IMAGE,
+            ExternalState::new(AccessType::Nothing),
+            ExternalState::new(AccessType::Present),
+        );
+        graph
+            .create_raster_pass("Clear")
+            .add_color_attachment(backbuffer);
+
+        assert!(graph.to_json().contains(concat!(
+            r#""import":{"current":{"accesses":["Nothing"],"layout":"Optimal"},"#,
+            r#""final":{"accesses":["Present"],"layout":"Optimal"}}"#
+        )));
+        assert!(graph
+            .to_dot()
+            .contains("n2 -> host_out [label=\"[Present] Optimal\" style=dotted]"));
+    }
+}
@@ -0,0 +1,142 @@
+use ash::vk;
+use vk_sync_fork::AccessType;
+
+use crate::{CompiledPass, ExternalState, ImageDesc, Res, TaskGraph};
+
+/// A resource kept from one frame to the next, as two resources of the graph.
+///
+/// Whoever executes the graph backs them with two allocations and swaps them every frame, so
+/// what `current` holds at the end of a frame is what `previous` holds during the next one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct History {
+    /// Written during this frame
+    pub current: Res,
+    /// What `current` held at the end of the previous frame, only meant to be read
+    pub previous: Res,
+}
+
+impl<R: Clone, C> TaskGraph<R, C> {
+    pub fn register_history_image(&mut self, tag: R, desc: ImageDesc) -> History {
+        let history = History {
+            current: self.register_resource_image(tag.clone(), desc),
+            previous: self.register_resource_image(tag, desc),
+        };
+        self.histories.push(history);
+        history
+    }
+
+    pub fn register_history_buffer(&mut self, tag: R, size: vk::DeviceSize) -> History {
+        let history = History {
+            current: self.register_resource_buffer(tag.clone(), size),
+            previous: self.register_resource_buffer(tag, size),
+        };
+        self.histories.push(history);
+        history
+    }
+}
+
+impl<R, C> TaskGraph<R, C> {
+    pub fn histories(&self) -> &[History] {
+        &self.histories
+    }
+
+    /// Whether the resource is either half of a history resource.
+    pub fn is_history(&self, res: Res) -> bool {
+        self.histories
+            .iter()
+            .any(|history| history.current == res || history.previous == res)
+    }
+
+    pub(crate) fn is_history_previous(&self, res: Res) -> bool {
+        self.histories.iter().any(|history| history.previous == res)
+    }
+
+    /// The state both halves of every history are left in between frames: the one its
+    /// previous content is first accessed in, so reading it doesn't need a barrier.
+    pub(crate) fn between_frame_states(
+        &self,
+        passes: &[CompiledPass],
+    ) -> Vec<(History, ExternalState)> {
+        self.histories
+            .iter()
+            .map(|&history| {
+                let first_read = passes.iter().find_map(|pass| {
+                    self.pass_accesses(pass)
+                        .into_iter()
+                        .find(|access| access.resource == history.previous)
+                });
+                let last_write = passes.iter().rev().find_map(|pass| {
+                    self.pass_accesses(pass)
+                        .into_iter()
+                        .find(|access| access.resource == history.current)
+                });
+
+                let state = match first_read.or(last_write) {
+                    Some(access) => ExternalState {
+                        accesses: access.accesses,
+                        layout: access.layout,
+                    },
+                    None => ExternalState::new(AccessType::Nothing),
+                };
+                (history, state)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vk_sync_fork::{AccessType, ImageLayout};
+
+    use crate::tests::{compile, Graph, HDR_IMAGE};
+
+    #[test]
+    fn history_is_kept_in_the_state_it_is_read_in() {
+        let mut graph = Graph::new();
+
+        let color = graph.register_resource_image("Color", HDR_IMAGE);
+        let accumulation = graph.register_history_image("Accumulation", HDR_IMAGE);
+
+        graph
+            .create_raster_pass("Scene")
+            .add_color_attachment(color);
+        graph
+            .create_ray_tracing_pass("Path tracing")
+            .add_sampled(color)
+            .add_sampled(accumulation.previous)
+            .add_output_storage_image(accumulation.current);
+
+        let compiled = compile(&graph);
+        assert!(graph.validate().is_empty());
+        assert!(compiled.culled_passes().is_empty());
+        assert!(
+            !graph.is_transient(accumulation.current) && !graph.is_transient(accumulation.previous)
+        );
+
+        let sampled = [AccessType::RayTracingShaderReadSampledImageOrUniformTexelBuffer];
+        let (_, state) = &compiled.history_states()[0];
+        assert_eq!(state.accesses, sampled);
+        assert_eq!(state.layout, ImageLayout::Optimal);
+
+        // Last frame already left it readable
+        let path_tracing = &compiled.passes()[1];
+        assert!(path_tracing
+            .barriers
+            .iter()
+            .all(|b| b.resource != accumulation.previous));
+        let current = path_tracing
+            .barriers
+            .iter()
+            .find(|b| b.resource == accumulation.current)
+            .unwrap();
+        assert_eq!(current.previous_accesses, sampled);
+        assert!(current.discard_contents);
+
+        // Made readable for the next frame
+        let next_frame = &compiled.final_barriers()[0];
+        assert_eq!(next_frame.resource, accumulation.current);
+        assert_eq!(next_frame.previous_layout, ImageLayout::General);
+        assert_eq!(next_frame.next_accesses, sampled);
+        assert_eq!(compiled.final_barriers().len(), 1);
+    }
+}
@@ -1,12 +1,55 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use ash::vk;
 use bitflags::bitflags;
-use petgraph::{
-    data::Element::Node,
-    prelude::{DiGraph, NodeIndex},
-};
+use petgraph::prelude::{DiGraph, EdgeIndex, NodeIndex};
+
+pub use aliasing::*;
+pub use barriers::*;
+pub use cache::*;
+pub use compile::*;
+pub use conditions::*;
+pub use history::*;
+pub use merge::*;
+pub use queues::*;
+pub use record::*;
+pub use resources::*;
+pub use validate::*;
+pub use vk_sync_fork;
+
+mod aliasing;
+mod barriers;
+mod cache;
+mod compile;
+mod conditions;
+mod cull;
+mod export;
+mod history;
+mod merge;
+mod queues;
+mod record;
+mod resources;
+mod validate;
+
+pub mod errors {
+    use thiserror::Error;
+
+    use crate::{Action, Res};
+
+    pub type Result<T> = std::result::Result<T, TaskGraphError>;
 
-#[derive(Copy, Clone)]
+    #[derive(Error, Debug, Clone, PartialEq, Eq)]
+    pub enum TaskGraphError {
+        #[error("Dependency cycle detected around pass {0:?}")]
+        Cycle(Action),
+        #[error("Passes {0:?} and {1:?} write {2:?} without any ordering between them")]
+        UnorderedWrites(Action, Action, Res),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Res(NodeIndex);
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Action(NodeIndex);
 
 #[derive(Debug)]
@@ -17,19 +60,21 @@ enum NodeId<R, C> {
 
 #[derive(Debug)]
 enum ResourceTy<Tag> {
-    Buffer(Tag),
-    Image(Tag),
+    Buffer(Tag, vk::DeviceSize),
+    Image(Tag, ImageDesc),
 }
 
 #[derive(Debug)]
 enum ActionTy<Tag> {
     External,
-    UploadToBuffer(Tag),
     Raster(Tag),
     Compute(Tag),
+    RayTracing(Tag),
+    Transfer(Tag),
+    AccelerationStructureBuild(Tag),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum EdgeAction {
     Read(ReadActionFlags),
     /// If the `bool` is set to `true`, the previous content will be preserved
@@ -37,19 +82,21 @@ enum EdgeAction {
 }
 
 bitflags! {
-    struct ReadActionFlags: u32 {
+    pub struct ReadActionFlags: u32 {
         const INPUT_ATTACHMENT = 1 << 0;
         const DEPTH_ATTACHMENT = 1 << 1;
         const SAMPLED = 1 << 2;
         const TRANSFER = 1 << 3;
         const STORAGE = 1 << 4;
+        const ACCELERATION_STRUCTURE = 1 << 5;
     }
 
-    struct WriteActionFlags: u32 {
+    pub struct WriteActionFlags: u32 {
         const COLOR_ATTACHMENT = 1 << 16;
         const DEPTH_ATTACHMENT = ReadActionFlags::DEPTH_ATTACHMENT.bits;
         const TRANSFER = 1 << 17;
         const STORAGE = 1 << 18;
+        const ACCELERATION_STRUCTURE = 1 << 19;
     }
 }
 
@@ -57,6 +104,26 @@ pub struct TaskGraph<R, C> {
     graph: DiGraph<NodeId<R, C>, EdgeAction>,
     external_before_node: NodeIndex,
     external_after_node: NodeIndex,
+    imports: HashMap<NodeIndex, Import>,
+    histories: Vec<History>,
+    /// Subresources touched by the edges that don't cover a whole image
+    ranges: HashMap<EdgeIndex, SubresourceRange>,
+    records: HashMap<NodeIndex, RecordFn>,
+    conditions: Conditions,
+    local_read: bool,
+}
+
+/// The states an imported resource is handed over in.
+#[derive(Debug, Clone, PartialEq)]
+struct Import {
+    current: ExternalState,
+    final_state: ExternalState,
+}
+
+impl<R, C> Default for TaskGraph<R, C> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<R, C> TaskGraph<R, C> {
@@ -69,19 +136,35 @@ impl<R, C> TaskGraph<R, C> {
             graph,
             external_before_node: before,
             external_after_node: after,
+            imports: HashMap::new(),
+            histories: Vec::new(),
+            ranges: HashMap::new(),
+            records: HashMap::new(),
+            conditions: Conditions::default(),
+            local_read: false,
         }
     }
 
-    pub fn register_resource_buffer(&mut self, tag: R) -> Res {
+    /// Let passes reading [input attachments](RasterPassBuilder::add_input_attachment) share a
+    /// rendering scope with the passes that rendered them, for executors that can read
+    /// attachments locally (`VK_KHR_dynamic_rendering_local_read`).
+    ///
+    /// Off by default: every pass then renders in its own scope, after a barrier, and reads its
+    /// input attachments as sampled images.
+    pub fn set_local_read(&mut self, enabled: bool) {
+        self.local_read = enabled;
+    }
+
+    pub fn register_resource_buffer(&mut self, tag: R, size: vk::DeviceSize) -> Res {
         Res(self
             .graph
-            .add_node(NodeId::Resource(ResourceTy::Buffer(tag))))
+            .add_node(NodeId::Resource(ResourceTy::Buffer(tag, size))))
     }
 
-    pub fn register_resource_image(&mut self, tag: R) -> Res {
+    pub fn register_resource_image(&mut self, tag: R, desc: ImageDesc) -> Res {
         Res(self
             .graph
-            .add_node(NodeId::Resource(ResourceTy::Image(tag))))
+            .add_node(NodeId::Resource(ResourceTy::Image(tag, desc))))
     }
 
     pub fn copy_from_host(&mut self, Res(to): Res) {
@@ -100,30 +183,176 @@ impl<R, C> TaskGraph<R, C> {
         );
     }
 
-    pub fn create_raster_pass(&mut self, tag: C) -> RasterPassBuilder<R, C> {
-        let pass = self.graph.add_node(NodeId::Action(ActionTy::Raster(tag)));
-        RasterPassBuilder {
-            graph: &mut self.graph,
-            pass,
+    /// Register an image living outside of the graph, like a swapchain image. It is in the
+    /// `current` state before the first pass and gets transitioned to `final_state` after the
+    /// last one.
+    pub fn import_image(
+        &mut self,
+        tag: R,
+        desc: ImageDesc,
+        current: ExternalState,
+        final_state: ExternalState,
+    ) -> Res {
+        let res = self.register_resource_image(tag, desc);
+        self.imports.insert(
+            res.0,
+            Import {
+                current,
+                final_state,
+            },
+        );
+        res
+    }
+
+    /// Register a buffer living outside of the graph, see [TaskGraph::import_image].
+    pub fn import_buffer(
+        &mut self,
+        tag: R,
+        size: vk::DeviceSize,
+        current: ExternalState,
+        final_state: ExternalState,
+    ) -> Res {
+        let res = self.register_resource_buffer(tag, size);
+        self.imports.insert(
+            res.0,
+            Import {
+                current,
+                final_state,
+            },
+        );
+        res
+    }
+
+    pub fn is_imported(&self, Res(res): Res) -> bool {
+        self.imports.contains_key(&res)
+    }
+
+    pub fn resource_tag(&self, Res(res): Res) -> &R {
+        match &self.graph[res] {
+            NodeId::Resource(ResourceTy::Buffer(tag, _) | ResourceTy::Image(tag, _)) => tag,
+            _ => unreachable!("Res handle doesn't point to a resource"),
+        }
+    }
+
+    pub fn pass_tag(&self, Action(pass): Action) -> &C {
+        match &self.graph[pass] {
+            NodeId::Action(
+                ActionTy::Raster(tag)
+                | ActionTy::Compute(tag)
+                | ActionTy::RayTracing(tag)
+                | ActionTy::Transfer(tag)
+                | ActionTy::AccelerationStructureBuild(tag),
+            ) => tag,
+            _ => unreachable!("Action handle doesn't point to a pass"),
         }
     }
 
-    pub fn create_compute_pass(&mut self, tag: C) -> ComputePassBuilder<R, C> {
+    pub fn create_raster_pass(&mut self, tag: C) -> RasterPassBuilder<'_, R, C> {
+        let pass = self.graph.add_node(NodeId::Action(ActionTy::Raster(tag)));
+        self.pass_builder(pass)
+    }
+
+    pub fn create_compute_pass(&mut self, tag: C) -> ComputePassBuilder<'_, R, C> {
         let pass = self.graph.add_node(NodeId::Action(ActionTy::Compute(tag)));
-        ComputePassBuilder {
+        self.pass_builder(pass)
+    }
+
+    pub fn create_ray_tracing_pass(&mut self, tag: C) -> RayTracingPassBuilder<'_, R, C> {
+        let pass = self
+            .graph
+            .add_node(NodeId::Action(ActionTy::RayTracing(tag)));
+        self.pass_builder(pass)
+    }
+
+    /// Copies and blits between resources of the graph.
+    pub fn create_transfer_pass(&mut self, tag: C) -> TransferPassBuilder<'_, R, C> {
+        let pass = self.graph.add_node(NodeId::Action(ActionTy::Transfer(tag)));
+        self.pass_builder(pass)
+    }
+
+    pub fn create_acceleration_structure_build_pass(
+        &mut self,
+        tag: C,
+    ) -> AccelerationStructureBuildPassBuilder<'_, R, C> {
+        let pass = self
+            .graph
+            .add_node(NodeId::Action(ActionTy::AccelerationStructureBuild(tag)));
+        self.pass_builder(pass)
+    }
+
+    fn pass_builder<K>(&mut self, pass: NodeIndex) -> PassBuilder<'_, R, C, K> {
+        PassBuilder {
             graph: &mut self.graph,
+            records: &mut self.records,
+            conditions: &mut self.conditions,
+            ranges: &mut self.ranges,
             pass,
+            kind: PhantomData,
         }
     }
 }
 
-pub struct RasterPassBuilder<'graph, R, C> {
+/// The kinds of pass a [PassBuilder] can build, which decide the resources it can use.
+pub mod kinds {
+    pub enum Raster {}
+    pub enum Compute {}
+    pub enum RayTracing {}
+    pub enum Transfer {}
+    pub enum AccelerationStructureBuild {}
+}
+
+pub type RasterPassBuilder<'graph, R, C> = PassBuilder<'graph, R, C, kinds::Raster>;
+pub type ComputePassBuilder<'graph, R, C> = PassBuilder<'graph, R, C, kinds::Compute>;
+pub type RayTracingPassBuilder<'graph, R, C> = PassBuilder<'graph, R, C, kinds::RayTracing>;
+pub type TransferPassBuilder<'graph, R, C> = PassBuilder<'graph, R, C, kinds::Transfer>;
+pub type AccelerationStructureBuildPassBuilder<'graph, R, C> =
+    PassBuilder<'graph, R, C, kinds::AccelerationStructureBuild>;
+
+/// Adds a pass of kind `K` to a graph.
+pub struct PassBuilder<'graph, R, C, K> {
     graph: &'graph mut DiGraph<NodeId<R, C>, EdgeAction>,
+    records: &'graph mut HashMap<NodeIndex, RecordFn>,
+    conditions: &'graph mut Conditions,
+    ranges: &'graph mut HashMap<EdgeIndex, SubresourceRange>,
     pass: NodeIndex,
+    kind: PhantomData<K>,
 }
 
-impl<R, C> RasterPassBuilder<'_, R, C> {
-    pub fn add_color_attachment(mut self, Res(res): Res) -> Self {
+impl<R, C, K> PassBuilder<'_, R, C, K> {
+    pub fn id(&self) -> Action {
+        Action(self.pass)
+    }
+
+    /// Set the callback recording the commands of the pass.
+    pub fn record(self, record: impl Fn(&PassContext) + Send + Sync + 'static) -> Self {
+        self.records.insert(self.pass, Box::new(record));
+        self
+    }
+
+    /// Only run the pass while `condition` holds, it is checked every time the graph is
+    /// compiled. Passes using what a disabled pass writes are disabled too, unless it is
+    /// forwarded with a passthrough.
+    pub fn enable_if(self, condition: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        self.conditions
+            .predicates
+            .insert(self.pass, Box::new(condition));
+        self
+    }
+
+    /// Use `input` in place of `output` when the pass is disabled, like for an effect that
+    /// can be turned off. Imported and history outputs can't be replaced.
+    pub fn passthrough(self, Res(input): Res, Res(output): Res) -> Self {
+        self.conditions
+            .passthroughs
+            .entry(self.pass)
+            .or_default()
+            .push((input, output));
+        self
+    }
+}
+
+impl<R, C> PassBuilder<'_, R, C, kinds::Raster> {
+    pub fn add_color_attachment(self, Res(res): Res) -> Self {
         self.graph.add_edge(
             self.pass,
             res,
@@ -132,7 +361,7 @@ impl<R, C> RasterPassBuilder<'_, R, C> {
         self
     }
 
-    pub fn add_depth_attachment(mut self, Res(res): Res, preserve: bool) -> Self {
+    pub fn add_depth_attachment(self, Res(res): Res, preserve: bool) -> Self {
         self.graph.add_edge(
             self.pass,
             res,
@@ -141,32 +370,214 @@ impl<R, C> RasterPassBuilder<'_, R, C> {
         self
     }
 
-    pub fn add_sampled(mut self, Res(res): Res) -> Self {
+    pub fn add_sampled(self, Res(res): Res) -> Self {
         self.graph
             .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::SAMPLED));
         self
     }
 
-    pub fn add_bound_buffer(mut self, Res(res): Res) -> Self {
+    /// Read what a previous pass rendered at the same pixel, which lets both passes share a
+    /// rendering scope when [local reads](TaskGraph::set_local_read) are enabled.
+    pub fn add_input_attachment(self, Res(res): Res) -> Self {
+        self.graph.add_edge(
+            res,
+            self.pass,
+            EdgeAction::Read(ReadActionFlags::INPUT_ATTACHMENT),
+        );
+        self
+    }
+
+    /// Render to some mips or layers of the image only.
+    pub fn add_color_attachment_range(self, Res(res): Res, range: SubresourceRange) -> Self {
+        let edge = self.graph.add_edge(
+            self.pass,
+            res,
+            EdgeAction::Write(WriteActionFlags::COLOR_ATTACHMENT, false),
+        );
+        self.ranges.insert(edge, range);
+        self
+    }
+
+    /// Render to some mips or layers of the image only.
+    pub fn add_depth_attachment_range(
+        self,
+        Res(res): Res,
+        range: SubresourceRange,
+        preserve: bool,
+    ) -> Self {
+        let edge = self.graph.add_edge(
+            self.pass,
+            res,
+            EdgeAction::Write(WriteActionFlags::DEPTH_ATTACHMENT, preserve),
+        );
+        self.ranges.insert(edge, range);
+        self
+    }
+
+    /// Sample some mips or layers of the image only.
+    pub fn add_sampled_range(self, Res(res): Res, range: SubresourceRange) -> Self {
+        let edge = self
+            .graph
+            .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::SAMPLED));
+        self.ranges.insert(edge, range);
+        self
+    }
+
+    pub fn add_bound_buffer(self, Res(res): Res) -> Self {
         self.graph
             .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::empty()));
         self
     }
 }
 
-pub struct ComputePassBuilder<'graph, R, C> {
-    graph: &'graph mut DiGraph<NodeId<R, C>, EdgeAction>,
-    pass: NodeIndex,
+impl<R, C> PassBuilder<'_, R, C, kinds::Compute> {
+    pub fn add_input_storage_buffer(self, Res(res): Res) -> Self {
+        self.graph
+            .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::STORAGE));
+        self
+    }
+
+    pub fn add_output_storage_buffer(self, Res(res): Res) -> Self {
+        self.graph.add_edge(
+            self.pass,
+            res,
+            EdgeAction::Write(WriteActionFlags::STORAGE, false),
+        );
+        self
+    }
 }
 
-impl<R, C> ComputePassBuilder<'_, R, C> {
-    pub fn add_input_storage_buffer(mut self, Res(res): Res) -> Self {
+impl<R, C> PassBuilder<'_, R, C, kinds::RayTracing> {
+    /// Trace rays against a top level acceleration structure.
+    pub fn add_acceleration_structure(self, Res(res): Res) -> Self {
+        self.graph.add_edge(
+            res,
+            self.pass,
+            EdgeAction::Read(ReadActionFlags::ACCELERATION_STRUCTURE),
+        );
+        self
+    }
+
+    pub fn add_sampled(self, Res(res): Res) -> Self {
+        self.graph
+            .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::SAMPLED));
+        self
+    }
+
+    pub fn add_bound_buffer(self, Res(res): Res) -> Self {
+        self.graph
+            .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::empty()));
+        self
+    }
+
+    pub fn add_input_storage_buffer(self, Res(res): Res) -> Self {
         self.graph
             .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::STORAGE));
         self
     }
 
-    pub fn add_output_storage_buffer(mut self, Res(res): Res) -> Self {
+    pub fn add_output_storage_image(self, Res(res): Res) -> Self {
+        self.graph.add_edge(
+            self.pass,
+            res,
+            EdgeAction::Write(WriteActionFlags::STORAGE, false),
+        );
+        self
+    }
+
+    /// Sample some mips or layers of the image only.
+    pub fn add_sampled_range(self, Res(res): Res, range: SubresourceRange) -> Self {
+        let edge = self
+            .graph
+            .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::SAMPLED));
+        self.ranges.insert(edge, range);
+        self
+    }
+
+    /// Write some mips or layers of the image only.
+    pub fn add_output_storage_image_range(self, Res(res): Res, range: SubresourceRange) -> Self {
+        let edge = self.graph.add_edge(
+            self.pass,
+            res,
+            EdgeAction::Write(WriteActionFlags::STORAGE, false),
+        );
+        self.ranges.insert(edge, range);
+        self
+    }
+}
+
+impl<R, C> PassBuilder<'_, R, C, kinds::Transfer> {
+    pub fn add_source(self, Res(res): Res) -> Self {
+        self.graph
+            .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::TRANSFER));
+        self
+    }
+
+    /// If `preserve` is set, the parts of the destination not covered by the copy are kept.
+    pub fn add_destination(self, Res(res): Res, preserve: bool) -> Self {
+        self.graph.add_edge(
+            self.pass,
+            res,
+            EdgeAction::Write(WriteActionFlags::TRANSFER, preserve),
+        );
+        self
+    }
+
+    /// Copy or blit from some mips or layers of the image only.
+    pub fn add_source_range(self, Res(res): Res, range: SubresourceRange) -> Self {
+        let edge = self
+            .graph
+            .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::TRANSFER));
+        self.ranges.insert(edge, range);
+        self
+    }
+
+    /// Copy or blit to some mips or layers of the image only, like when generating mips.
+    pub fn add_destination_range(
+        self,
+        Res(res): Res,
+        range: SubresourceRange,
+        preserve: bool,
+    ) -> Self {
+        let edge = self.graph.add_edge(
+            self.pass,
+            res,
+            EdgeAction::Write(WriteActionFlags::TRANSFER, preserve),
+        );
+        self.ranges.insert(edge, range);
+        self
+    }
+}
+
+impl<R, C> PassBuilder<'_, R, C, kinds::AccelerationStructureBuild> {
+    /// Vertices, indices or instances the acceleration structure is built from.
+    pub fn add_geometry_buffer(self, Res(res): Res) -> Self {
+        self.graph
+            .add_edge(res, self.pass, EdgeAction::Read(ReadActionFlags::empty()));
+        self
+    }
+
+    /// Bottom level acceleration structures referenced by the instances of a top level one.
+    pub fn add_input_acceleration_structure(self, Res(res): Res) -> Self {
+        self.graph.add_edge(
+            res,
+            self.pass,
+            EdgeAction::Read(ReadActionFlags::ACCELERATION_STRUCTURE),
+        );
+        self
+    }
+
+    /// If `update` is set, the acceleration structure is refit instead of built from scratch.
+    pub fn add_output_acceleration_structure(self, Res(res): Res, update: bool) -> Self {
+        self.graph.add_edge(
+            self.pass,
+            res,
+            EdgeAction::Write(WriteActionFlags::ACCELERATION_STRUCTURE, update),
+        );
+        self
+    }
+
+    pub fn add_scratch_buffer(self, Res(res): Res) -> Self {
         self.graph.add_edge(
             self.pass,
             res,
@@ -177,21 +588,33 @@ impl<R, C> ComputePassBuilder<'_, R, C> {
 }
 
 #[cfg(test)]
-mod tests {
-    use crate::TaskGraph;
-    use petgraph::dot::{Config, Dot};
+pub(crate) mod tests {
+    use crate::{CompiledGraph, ImageDesc, TaskGraph};
+    use ash::vk;
+
+    // Shared by the tests of every module
+
+    pub(crate) const IMAGE: ImageDesc = ImageDesc::new_2d(1920, 1080, vk::Format::R8G8B8A8_UNORM);
+    pub(crate) const HDR_IMAGE: ImageDesc =
+        ImageDesc::new_2d(1920, 1080, vk::Format::R16G16B16A16_SFLOAT);
+
+    pub(crate) type Graph = TaskGraph<&'static str, &'static str>;
+
+    pub(crate) fn compile(graph: &Graph) -> CompiledGraph {
+        graph.compile().unwrap()
+    }
 
     #[test]
     fn yolo() {
-        let mut graph = TaskGraph::new();
+        let mut graph = Graph::new();
 
-        let model = graph.register_resource_buffer("Model 1");
-        let backbuffer = graph.register_resource_image("Backbuffer");
-        let depth = graph.register_resource_image("Depth");
-        let texture = graph.register_resource_image("Texture 1");
+        let model = graph.register_resource_buffer("Model 1", 1024);
+        let backbuffer = graph.register_resource_image("Backbuffer", IMAGE);
+        let depth = graph.register_resource_image("Depth", IMAGE);
+        let texture = graph.register_resource_image("Texture 1", IMAGE);
 
-        let in_compute = graph.register_resource_buffer("In compute");
-        let out_compute = graph.register_resource_buffer("Out compute");
+        let in_compute = graph.register_resource_buffer("In compute", 1024);
+        let out_compute = graph.register_resource_buffer("Out compute", 1024);
 
         graph.copy_from_host(model);
         graph
@@ -208,12 +631,32 @@ mod tests {
             .add_output_storage_buffer(out_compute);
         graph.copy_to_host(out_compute);
 
-        println!("digraph {{");
-        println!(" rankdir=LR");
-        println!(
-            "{:?}",
-            Dot::with_config(&graph.graph, &[Config::GraphContentOnly])
+        // Nothing reads what the raster pass renders, it is culled
+        assert_eq!(
+            graph.to_dot(),
+            r#"digraph {
+    rankdir=LR
+    host_in [label="Host" shape=diamond]
+    host_out [label="Host" shape=diamond]
+    n8 [label="Raster\nRaster" shape=box style=dashed]
+    n9 [label="Compute\nCompute" shape=box]
+    n2 [label="Model 1\nBuffer 1024 bytes" shape=ellipse]
+    n3 [label="Backbuffer\nImage 1920x1080x1 R8G8B8A8_UNORM" shape=ellipse]
+    n4 [label="Depth\nImage 1920x1080x1 R8G8B8A8_UNORM" shape=ellipse]
+    n5 [label="Texture 1\nImage 1920x1080x1 R8G8B8A8_UNORM" shape=ellipse]
+    n6 [label="In compute\nBuffer 1024 bytes" shape=ellipse]
+    n7 [label="Out compute\nBuffer 1024 bytes" shape=ellipse]
+    host_in -> n2 [label="TRANSFER"]
+    n2 -> n8 [label="BOUND"]
+    n8 -> n3 [label="COLOR_ATTACHMENT"]
+    n8 -> n4 [label="DEPTH_ATTACHMENT"]
+    n5 -> n8 [label="SAMPLED"]
+    host_in -> n6 [label="TRANSFER"]
+    n6 -> n9 [label="STORAGE"]
+    n9 -> n7 [label="STORAGE"]
+    n7 -> host_out [label="TRANSFER"]
+}
+"#
         );
-        println!("}}");
     }
 }
@@ -0,0 +1,363 @@
+use std::{collections::HashSet, ops::Range};
+
+use ash::vk;
+
+use crate::{
+    Barrier, CompiledGraph, CompiledPass, PassKind, ReadActionFlags, Res, TaskGraph,
+    WriteActionFlags,
+};
+
+/// How a raster pass uses its rendering scope, for passes merged together on tile-based GPUs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RenderScope {
+    /// Begins and ends its own rendering scope
+    Own,
+    /// Begins a scope the next passes continue
+    Begin,
+    /// Renders inside the scope of the previous pass, reading its attachments locally
+    Continue,
+    /// Renders inside the scope of the previous pass and ends it
+    End,
+}
+
+impl CompiledGraph {
+    /// Runs of consecutive raster passes that can share one rendering scope, as ranges of
+    /// [passes](Self::passes).
+    pub fn render_groups(&self) -> &[Range<usize>] {
+        &self.render_groups
+    }
+
+    /// How the pass at `index` in [passes](Self::passes) uses its rendering scope.
+    pub fn render_scope(&self, index: usize) -> RenderScope {
+        match self
+            .render_groups
+            .iter()
+            .find(|group| group.contains(&index))
+        {
+            None => RenderScope::Own,
+            Some(group) if index == group.start => RenderScope::Begin,
+            Some(group) if index == group.end - 1 => RenderScope::End,
+            Some(_) => RenderScope::Continue,
+        }
+    }
+}
+
+const READ_ATTACHMENT: ReadActionFlags = ReadActionFlags::from_bits_truncate(
+    ReadActionFlags::INPUT_ATTACHMENT.bits() | ReadActionFlags::DEPTH_ATTACHMENT.bits(),
+);
+const WRITE_ATTACHMENT: WriteActionFlags = WriteActionFlags::from_bits_truncate(
+    WriteActionFlags::COLOR_ATTACHMENT.bits() | WriteActionFlags::DEPTH_ATTACHMENT.bits(),
+);
+
+impl<R, C> TaskGraph<R, C> {
+    /// How a pass reads with `flags`: without [local reads](Self::set_local_read), its input
+    /// attachments are sampled from outside the scope that rendered them.
+    pub(crate) fn effective_read_flags(&self, flags: ReadActionFlags) -> ReadActionFlags {
+        if self.local_read || !flags.contains(ReadActionFlags::INPUT_ATTACHMENT) {
+            return flags;
+        }
+        (flags - ReadActionFlags::INPUT_ATTACHMENT) | ReadActionFlags::SAMPLED
+    }
+
+    /// Group consecutive raster passes rendering at the same extent, when each one reads what
+    /// the previous ones wrote only as input attachments at the same pixel.
+    pub(crate) fn render_groups(&self, passes: &[CompiledPass]) -> Vec<Range<usize>> {
+        if !self.local_read {
+            return Vec::new();
+        }
+
+        let mut groups = Vec::<Range<usize>>::new();
+
+        for (i, pass) in passes.iter().enumerate() {
+            match groups.last_mut() {
+                Some(group) if group.end == i && self.can_merge(&passes[group.clone()], pass) => {
+                    group.end = i + 1
+                }
+                _ => groups.push(i..i + 1),
+            }
+        }
+
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+
+    fn can_merge(&self, group: &[CompiledPass], next: &CompiledPass) -> bool {
+        let first = &group[0];
+        if first.kind != PassKind::Raster
+            || next.kind != PassKind::Raster
+            || first.queue != next.queue
+        {
+            return false;
+        }
+
+        let extent = self.attachment_extent(next);
+        if extent.is_none()
+            || group
+                .iter()
+                .any(|pass| self.attachment_extent(pass) != extent)
+        {
+            return false;
+        }
+
+        // Anything shared with the group must be an attachment, only read at the same pixel
+        let mut local_read = false;
+        for read in &next.reads {
+            if !touches(group, read.resource) {
+                continue;
+            }
+            let written = group.iter().flat_map(|pass| &pass.writes).any(|write| {
+                write.resource == read.resource
+                    && write.range == read.range
+                    && WRITE_ATTACHMENT.contains(write.flags)
+            });
+            if !written || !READ_ATTACHMENT.contains(read.flags) {
+                return false;
+            }
+            local_read |= read.flags.contains(ReadActionFlags::INPUT_ATTACHMENT);
+        }
+        for write in &next.writes {
+            if touches(group, write.resource) && !WRITE_ATTACHMENT.contains(write.flags) {
+                return false;
+            }
+        }
+
+        local_read
+    }
+
+    /// The extent of every attachment of the pass, if they all have the same.
+    fn attachment_extent(&self, pass: &CompiledPass) -> Option<vk::Extent3D> {
+        let reads = pass
+            .reads
+            .iter()
+            .filter(|read| read.flags.intersects(READ_ATTACHMENT))
+            .map(|read| (read.resource, read.range.base_mip_level));
+        let writes = pass
+            .writes
+            .iter()
+            .filter(|write| write.flags.intersects(WRITE_ATTACHMENT))
+            .map(|write| (write.resource, write.range.base_mip_level));
+
+        let mut extents = reads.chain(writes).map(|(resource, mip)| {
+            self.image_desc(resource).map(|desc| vk::Extent3D {
+                width: (desc.extent.width >> mip).max(1),
+                height: (desc.extent.height >> mip).max(1),
+                depth: (desc.extent.depth >> mip).max(1),
+            })
+        });
+
+        let extent = extents.next()??;
+        extents.all(|other| other == Some(extent)).then_some(extent)
+    }
+}
+
+fn touches(group: &[CompiledPass], resource: Res) -> bool {
+    group.iter().any(|pass| {
+        pass.reads.iter().any(|read| read.resource == resource)
+            || pass.writes.iter().any(|write| write.resource == resource)
+    })
+}
+
+/// The resources used by several passes of a group, which stay in the general layout for the
+/// whole group so no layout transition happens inside the rendering scope.
+pub(crate) fn shared_resources(group: &[CompiledPass]) -> HashSet<Res> {
+    let mut shared = HashSet::new();
+    for (i, pass) in group.iter().enumerate() {
+        let resources = pass
+            .reads
+            .iter()
+            .map(|read| read.resource)
+            .chain(pass.writes.iter().map(|write| write.resource));
+        for resource in resources {
+            if touches(&group[..i], resource) {
+                shared.insert(resource);
+            }
+        }
+    }
+    shared
+}
+
+/// Queue ownership can't be released in the middle of a rendering scope, end the groups
+/// after the passes releasing something.
+pub(crate) fn split_at_releases(
+    groups: Vec<Range<usize>>,
+    passes: &[CompiledPass],
+    final_barriers: &[Barrier],
+) -> Vec<Range<usize>> {
+    let releases = passes
+        .iter()
+        .flat_map(|pass| &pass.barriers)
+        .chain(final_barriers)
+        .filter_map(|barrier| barrier.queue_transfer)
+        .map(|transfer| transfer.release_after)
+        .collect::<HashSet<_>>();
+
+    let mut split = Vec::new();
+    for group in groups {
+        let mut start = group.start;
+        for i in group.clone() {
+            if releases.contains(&i) || i == group.end - 1 {
+                split.push(start..i + 1);
+                start = i + 1;
+            }
+        }
+    }
+
+    split.retain(|group| group.len() > 1);
+    split
+}
+
+/// Move the barriers of the passes continuing a group before the first pass of the group,
+/// except the ones between passes of the group which can be recorded inside the scope.
+///
+/// The group doesn't touch the resources they are about, so they can happen earlier.
+pub(crate) fn hoist_barriers(passes: &mut [CompiledPass], groups: &[Range<usize>]) {
+    for group in groups {
+        let shared = shared_resources(&passes[group.clone()]);
+
+        let mut hoisted = Vec::new();
+        for pass in &mut passes[group.start + 1..group.end] {
+            let (local, other) = pass
+                .barriers
+                .drain(..)
+                .partition(|barrier| shared.contains(&barrier.resource));
+            pass.barriers = local;
+            hoisted.extend::<Vec<_>>(other);
+        }
+        passes[group.start].barriers.extend(hoisted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use vk_sync_fork::{AccessType, ImageLayout};
+
+    use crate::{
+        tests::{compile, Graph, IMAGE},
+        ImageDesc, RenderScope, Res,
+    };
+
+    const DEPTH: ImageDesc = ImageDesc::new_2d(1920, 1080, vk::Format::D32_SFLOAT);
+    const SHADOW_MAP: ImageDesc = ImageDesc::new_2d(2048, 2048, vk::Format::D32_SFLOAT);
+
+    /// Shadows, then a gbuffer lit at the same pixel. Returns the shadow map, albedo, normals,
+    /// depth and lit images.
+    fn deferred(graph: &mut Graph) -> [Res; 5] {
+        let shadow_map = graph.register_resource_image("Shadow map", SHADOW_MAP);
+        let albedo = graph.register_resource_image("Albedo", IMAGE);
+        let normals = graph.register_resource_image("Normals", IMAGE);
+        let depth = graph.register_resource_image("Depth", DEPTH);
+        let lit = graph.register_resource_image("Lit", IMAGE);
+
+        graph
+            .create_raster_pass("Shadows")
+            .add_depth_attachment(shadow_map, false);
+        graph
+            .create_raster_pass("GBuffer")
+            .add_color_attachment(albedo)
+            .add_color_attachment(normals)
+            .add_depth_attachment(depth, false);
+        graph
+            .create_raster_pass("Lighting")
+            .add_input_attachment(albedo)
+            .add_input_attachment(normals)
+            .add_depth_attachment(depth, true)
+            .add_sampled(shadow_map)
+            .add_color_attachment(lit);
+        graph.copy_to_host(lit);
+
+        [shadow_map, albedo, normals, depth, lit]
+    }
+
+    #[test]
+    fn deferred_lighting_is_merged_with_the_gbuffer_pass() {
+        let mut graph = Graph::new();
+        graph.set_local_read(true);
+        let [shadow_map, albedo, normals, depth, lit] = deferred(&mut graph);
+
+        let compiled = compile(&graph);
+        assert_eq!(compiled.render_groups().len(), 1);
+        assert_eq!(compiled.render_groups()[0], 1..3);
+        assert_eq!(compiled.render_scope(0), RenderScope::Own);
+        assert_eq!(compiled.render_scope(1), RenderScope::Begin);
+        assert_eq!(compiled.render_scope(2), RenderScope::End);
+
+        // Only the attachments of the group are left inside the scope, in the general layout
+        let lighting = &compiled.passes()[2];
+        assert_eq!(lighting.barriers.len(), 3);
+        for barrier in &lighting.barriers {
+            assert!([albedo, normals, depth].contains(&barrier.resource));
+            assert_eq!(barrier.previous_layout, ImageLayout::General);
+            assert_eq!(barrier.next_layout, ImageLayout::General);
+        }
+        let normals_read = lighting
+            .barriers
+            .iter()
+            .find(|b| b.resource == normals)
+            .unwrap();
+        assert_eq!(
+            normals_read.next_accesses,
+            [AccessType::FragmentShaderReadColorInputAttachment]
+        );
+
+        let gbuffer = &compiled.passes()[1];
+        assert!(gbuffer.barriers.iter().any(|b| b.resource == shadow_map));
+        assert!(gbuffer.barriers.iter().any(|b| b.resource == lit));
+        assert!(graph
+            .image_usage(normals)
+            .contains(vk::ImageUsageFlags::INPUT_ATTACHMENT));
+    }
+
+    #[test]
+    fn input_attachments_are_sampled_without_local_read() {
+        let mut graph = Graph::new();
+        let [_, _, normals, _, _] = deferred(&mut graph);
+
+        let compiled = compile(&graph);
+        assert!(compiled.render_groups().is_empty());
+        assert_eq!(compiled.render_scope(1), RenderScope::Own);
+        assert_eq!(compiled.render_scope(2), RenderScope::Own);
+
+        // An ordinary barrier between the passes, out of the attachment layout
+        let normals_read = compiled.passes()[2]
+            .barriers
+            .iter()
+            .find(|b| b.resource == normals)
+            .unwrap();
+        assert_eq!(
+            normals_read.previous_accesses,
+            [AccessType::ColorAttachmentWrite]
+        );
+        assert_eq!(
+            normals_read.next_accesses,
+            [AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer]
+        );
+        assert_eq!(normals_read.next_layout, ImageLayout::Optimal);
+
+        let usage = graph.image_usage(normals);
+        assert!(usage.contains(vk::ImageUsageFlags::SAMPLED));
+        assert!(!usage.contains(vk::ImageUsageFlags::INPUT_ATTACHMENT));
+    }
+
+    #[test]
+    fn sampled_outputs_are_not_merged() {
+        let mut graph = Graph::new();
+        graph.set_local_read(true);
+
+        let color = graph.register_resource_image("Color", IMAGE);
+        let blurred = graph.register_resource_image("Blurred", IMAGE);
+
+        graph
+            .create_raster_pass("Scene")
+            .add_color_attachment(color);
+        graph
+            .create_raster_pass("Blur")
+            .add_sampled(color)
+            .add_color_attachment(blurred);
+        graph.copy_to_host(blurred);
+
+        let compiled = compile(&graph);
+        assert!(compiled.render_groups().is_empty());
+        assert_eq!(compiled.render_scope(1), RenderScope::Own);
+    }
+}
@@ -0,0 +1,256 @@
+use std::ops::Range;
+
+use petgraph::prelude::NodeIndex;
+
+use crate::{barriers::PassKind, CompiledPass, Res, TaskGraph};
+
+/// The queue a pass is submitted to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum QueueType {
+    Graphics,
+    /// Preferably a dedicated compute queue, running concurrently with the graphics work
+    Compute,
+}
+
+/// Moves a resource from one queue to another, needed when they are from different families.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QueueTransfer {
+    pub from: QueueType,
+    pub to: QueueType,
+    /// Index of the last pass using the resource on the source queue, which must release it.
+    /// The release can be skipped when the barrier discards the content.
+    pub release_after: usize,
+}
+
+/// A run of consecutive passes of the schedule going to the same queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submission {
+    pub queue: QueueType,
+    /// Indices of the passes in the schedule
+    pub passes: Range<usize>,
+    /// Indices of the submissions from other queues that must signal before this one starts
+    pub waits: Vec<usize>,
+}
+
+impl<R, C> TaskGraph<R, C> {
+    /// Compute passes that are completely independent from the raster passes go to the
+    /// compute queue, everything else goes to the graphics queue.
+    ///
+    /// Passes using a history resource stay on the graphics queue, which owns them between
+    /// frames.
+    pub(crate) fn assign_queues(&self, order: &[NodeIndex]) -> Vec<QueueType> {
+        let raster = order
+            .iter()
+            .copied()
+            .filter(|&pass| self.pass_kind(pass) == PassKind::Raster)
+            .collect::<Vec<_>>();
+
+        order
+            .iter()
+            .map(|&pass| {
+                let is_async = self.pass_kind(pass) == PassKind::Compute
+                    && !self.uses_history(pass)
+                    && raster.iter().all(|&raster| {
+                        !self.depends_on(pass, raster) && !self.depends_on(raster, pass)
+                    });
+
+                if is_async {
+                    QueueType::Compute
+                } else {
+                    QueueType::Graphics
+                }
+            })
+            .collect()
+    }
+
+    fn uses_history(&self, pass: NodeIndex) -> bool {
+        self.graph
+            .neighbors_undirected(pass)
+            .any(|resource| self.is_history(Res(resource)))
+    }
+}
+
+/// Cut the schedule every time the queue changes, each submission waits for the latest
+/// submission of the other queues it takes resources from.
+pub(crate) fn split_submissions(passes: &[CompiledPass]) -> Vec<Submission> {
+    let mut submissions = Vec::<Submission>::new();
+
+    for (i, pass) in passes.iter().enumerate() {
+        match submissions.last_mut() {
+            Some(submission) if submission.queue == pass.queue => submission.passes.end = i + 1,
+            _ => submissions.push(Submission {
+                queue: pass.queue,
+                passes: i..i + 1,
+                waits: Vec::new(),
+            }),
+        }
+    }
+
+    let submission_of = |pass: usize| {
+        submissions
+            .iter()
+            .position(|submission| submission.passes.contains(&pass))
+            .unwrap()
+    };
+
+    let waits = submissions
+        .iter()
+        .map(|submission| {
+            let mut waits = Vec::<usize>::new();
+            let transfers = passes[submission.passes.clone()]
+                .iter()
+                .flat_map(|pass| &pass.barriers)
+                .filter_map(|barrier| barrier.queue_transfer);
+
+            for transfer in transfers {
+                let from = submission_of(transfer.release_after);
+                // Only the latest one matters, the queue signals in submission order
+                match waits
+                    .iter_mut()
+                    .find(|wait| submissions[**wait].queue == transfer.from)
+                {
+                    Some(wait) => *wait = (*wait).max(from),
+                    None => waits.push(from),
+                }
+            }
+
+            waits.sort_unstable();
+            waits
+        })
+        .collect::<Vec<_>>();
+
+    for (submission, waits) in submissions.iter_mut().zip(waits) {
+        submission.waits = waits;
+    }
+
+    submissions
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        tests::{compile, Graph, IMAGE},
+        QueueType,
+    };
+
+    #[test]
+    fn independent_compute_goes_async() {
+        let mut graph = Graph::new();
+
+        let color = graph.register_resource_image("Color", IMAGE);
+        let particles = graph.register_resource_buffer("Particles", 1024);
+        let simulated = graph.register_resource_buffer("Simulated", 1024);
+        let culled = graph.register_resource_buffer("Culled", 1024);
+
+        graph.copy_from_host(particles);
+        graph
+            .create_compute_pass("Simulate")
+            .add_input_storage_buffer(particles)
+            .add_output_storage_buffer(simulated);
+        graph
+            .create_compute_pass("Cull")
+            .add_input_storage_buffer(simulated)
+            .add_output_storage_buffer(culled);
+        graph.create_raster_pass("Main").add_color_attachment(color);
+        graph.copy_to_host(color);
+        graph.copy_to_host(culled);
+
+        let compiled = compile(&graph);
+        let queues = compiled
+            .passes()
+            .iter()
+            .map(|pass| (*graph.pass_tag(pass.action), pass.queue))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            queues,
+            [
+                ("Simulate", QueueType::Compute),
+                ("Cull", QueueType::Compute),
+                ("Main", QueueType::Graphics),
+            ]
+        );
+
+        let submissions = compiled.submissions();
+        assert_eq!(submissions.len(), 2);
+        assert_eq!(submissions[0].queue, QueueType::Compute);
+        assert_eq!(submissions[0].passes, 0..2);
+        assert!(submissions[1].waits.is_empty());
+    }
+
+    #[test]
+    fn resources_shared_across_queues_are_transferred() {
+        let mut graph = Graph::new();
+
+        let visibility = graph.register_resource_buffer("Visibility", 1024);
+        let color = graph.register_resource_image("Color", IMAGE);
+        let stats = graph.register_resource_buffer("Stats", 1024);
+
+        // Culling feeds both the main pass and the stats gathering, which is unrelated to rasterizing
+        graph
+            .create_compute_pass("Cull")
+            .add_output_storage_buffer(visibility);
+        graph
+            .create_raster_pass("Main")
+            .add_bound_buffer(visibility)
+            .add_color_attachment(color);
+        graph
+            .create_compute_pass("Stats")
+            .add_input_storage_buffer(visibility)
+            .add_output_storage_buffer(stats);
+        graph.copy_to_host(color);
+        graph.copy_to_host(stats);
+
+        let compiled = compile(&graph);
+        let passes = compiled.passes();
+
+        assert_eq!(passes[0].queue, QueueType::Graphics);
+        assert_eq!(passes[1].queue, QueueType::Graphics);
+        assert_eq!(passes[2].queue, QueueType::Compute);
+
+        let acquire = passes[2]
+            .barriers
+            .iter()
+            .find(|barrier| barrier.resource == visibility)
+            .unwrap();
+        let transfer = acquire.queue_transfer.unwrap();
+        assert_eq!(transfer.from, QueueType::Graphics);
+        assert_eq!(transfer.to, QueueType::Compute);
+        assert_eq!(transfer.release_after, 1);
+
+        // Back to graphics for the readback
+        let readback = compiled
+            .final_barriers()
+            .iter()
+            .find(|barrier| barrier.resource == stats)
+            .unwrap();
+        assert_eq!(readback.queue_transfer.unwrap().from, QueueType::Compute);
+
+        let submissions = compiled.submissions();
+        assert_eq!(submissions.len(), 2);
+        assert_eq!(submissions[1].waits, [0]);
+    }
+
+    #[test]
+    fn history_users_stay_on_graphics() {
+        let mut graph = Graph::new();
+
+        let color = graph.register_resource_image("Color", IMAGE);
+        let luminance = graph.register_history_buffer("Luminance", 4);
+
+        graph.create_raster_pass("Main").add_color_attachment(color);
+        graph
+            .create_compute_pass("Adapt")
+            .add_input_storage_buffer(luminance.previous)
+            .add_output_storage_buffer(luminance.current);
+        graph.copy_to_host(color);
+
+        let compiled = compile(&graph);
+        assert!(compiled
+            .passes()
+            .iter()
+            .all(|pass| pass.queue == QueueType::Graphics));
+        assert_eq!(compiled.submissions().len(), 1);
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::{Action, RenderScope, Res, SubresourceRange, TaskGraph};
+
+/// Records the commands of a pass.
+pub type RecordFn = Box<dyn Fn(&PassContext) + Send + Sync>;
+
+/// Everything a pass gets to record its commands.
+pub struct PassContext<'a> {
+    pub device: &'a ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+    pub resources: &'a ResolvedResources,
+    /// Whether the pass begins and ends its own rendering scope or shares one with its
+    /// neighbours
+    pub render_scope: RenderScope,
+}
+
+/// The Vulkan objects behind an image of the graph.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResolvedImage {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub aspect: vk::ImageAspectFlags,
+}
+
+/// The Vulkan objects standing for the resources of a graph, filled by whoever executes it.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedResources {
+    images: HashMap<Res, ResolvedImage>,
+    range_views: HashMap<(Res, SubresourceRange), vk::ImageView>,
+    buffers: HashMap<Res, vk::Buffer>,
+}
+
+impl ResolvedResources {
+    pub fn bind_image(&mut self, res: Res, image: ResolvedImage) {
+        self.images.insert(res, image);
+    }
+
+    /// A view of only some mips or layers of the image, for the passes using them alone.
+    pub fn bind_image_range_view(
+        &mut self,
+        res: Res,
+        range: SubresourceRange,
+        view: vk::ImageView,
+    ) {
+        self.range_views.insert((res, range), view);
+    }
+
+    pub fn bind_buffer(&mut self, res: Res, buffer: vk::Buffer) {
+        self.buffers.insert(res, buffer);
+    }
+
+    /// Make `res` resolve to whatever `to` is bound to, for resources forwarded by a disabled
+    /// passthrough pass.
+    pub fn bind_forwarded(&mut self, res: Res, to: Res) {
+        if let Some(image) = self.images.get(&to).copied() {
+            self.images.insert(res, image);
+        }
+        if let Some(buffer) = self.buffers.get(&to).copied() {
+            self.buffers.insert(res, buffer);
+        }
+
+        let range_views = self
+            .range_views
+            .iter()
+            .filter(|((bound, _), _)| *bound == to)
+            .map(|(&(_, range), &view)| ((res, range), view))
+            .collect::<Vec<_>>();
+        self.range_views.extend(range_views);
+    }
+
+    pub fn resolved_image(&self, res: Res) -> Option<&ResolvedImage> {
+        self.images.get(&res)
+    }
+
+    pub fn resolved_buffer(&self, res: Res) -> Option<vk::Buffer> {
+        self.buffers.get(&res).copied()
+    }
+
+    /// Panics if the image isn't bound.
+    pub fn image(&self, res: Res) -> vk::Image {
+        self.expect_image(res).image
+    }
+
+    /// Panics if the image isn't bound.
+    pub fn image_view(&self, res: Res) -> vk::ImageView {
+        self.expect_image(res).view
+    }
+
+    /// Panics if no view of this range is bound.
+    pub fn image_range_view(&self, res: Res, range: SubresourceRange) -> vk::ImageView {
+        *self
+            .range_views
+            .get(&(res, range))
+            .unwrap_or_else(|| panic!("No view of {:?} of image {:?} is bound", range, res))
+    }
+
+    /// Panics if the buffer isn't bound.
+    pub fn buffer(&self, res: Res) -> vk::Buffer {
+        self.resolved_buffer(res)
+            .unwrap_or_else(|| panic!("Buffer {:?} isn't bound", res))
+    }
+
+    fn expect_image(&self, res: Res) -> &ResolvedImage {
+        self.resolved_image(res)
+            .unwrap_or_else(|| panic!("Image {:?} isn't bound", res))
+    }
+}
+
+impl<R, C> TaskGraph<R, C> {
+    /// Run the record callback of the pass, if it has one.
+    pub fn record_pass(&self, Action(pass): Action, ctx: &PassContext) {
+        if let Some(record) = self.records.get(&pass) {
+            record(ctx);
+        }
+    }
+}
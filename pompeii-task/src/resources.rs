@@ -0,0 +1,329 @@
+use ash::vk;
+use petgraph::{prelude::EdgeIndex, visit::EdgeRef, Direction};
+
+use crate::{
+    ActionTy, EdgeAction, NodeId, ReadActionFlags, Res, ResourceTy, TaskGraph, WriteActionFlags,
+};
+
+/// Everything needed to create an image, except its usage which is derived from the graph.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ImageDesc {
+    pub extent: vk::Extent3D,
+    pub format: vk::Format,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl ImageDesc {
+    /// Single sampled 2D image without mips.
+    pub const fn new_2d(width: u32, height: u32, format: vk::Format) -> Self {
+        Self {
+            extent: vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+            format,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+}
+
+impl ImageDesc {
+    /// Every mip and layer of the image.
+    pub const fn full_range(&self) -> SubresourceRange {
+        SubresourceRange {
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: self.array_layers,
+        }
+    }
+}
+
+/// Mip levels and array layers of an image. Buffers are a single subresource, the default one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubresourceRange {
+    pub base_mip_level: u32,
+    pub level_count: u32,
+    pub base_array_layer: u32,
+    pub layer_count: u32,
+}
+
+impl Default for SubresourceRange {
+    fn default() -> Self {
+        Self::mip(0)
+    }
+}
+
+impl SubresourceRange {
+    /// A single mip of a single layer image.
+    pub const fn mip(level: u32) -> Self {
+        Self {
+            base_mip_level: level,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+
+    /// A single layer of an image without mips, like a cubemap face or a shadow cascade.
+    pub const fn layer(layer: u32) -> Self {
+        Self {
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: layer,
+            layer_count: 1,
+        }
+    }
+
+    pub fn overlaps(&self, other: &SubresourceRange) -> bool {
+        self.base_mip_level < other.base_mip_level + other.level_count
+            && other.base_mip_level < self.base_mip_level + self.level_count
+            && self.base_array_layer < other.base_array_layer + other.layer_count
+            && other.base_array_layer < self.base_array_layer + self.layer_count
+    }
+
+    pub fn to_vk(self, aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: self.base_mip_level,
+            level_count: self.level_count,
+            base_array_layer: self.base_array_layer,
+            layer_count: self.layer_count,
+        }
+    }
+}
+
+impl<R, C> TaskGraph<R, C> {
+    /// The whole resource, every subresource of an image.
+    pub fn full_range(&self, Res(res): Res) -> SubresourceRange {
+        match &self.graph[res] {
+            NodeId::Resource(ResourceTy::Image(_, desc)) => desc.full_range(),
+            _ => SubresourceRange::default(),
+        }
+    }
+
+    /// The part of the resource an edge touches.
+    pub(crate) fn edge_range(&self, edge: EdgeIndex, res: Res) -> SubresourceRange {
+        self.ranges
+            .get(&edge)
+            .copied()
+            .unwrap_or_else(|| self.full_range(res))
+    }
+
+    /// Every distinct range of the image used by the passes, besides the whole image.
+    pub fn image_ranges(&self, Res(res): Res) -> Vec<SubresourceRange> {
+        let mut ranges = self
+            .graph
+            .edges_directed(res, Direction::Incoming)
+            .chain(self.graph.edges_directed(res, Direction::Outgoing))
+            .filter_map(|edge| self.ranges.get(&edge.id()).copied())
+            .collect::<Vec<_>>();
+        ranges.sort_unstable();
+        ranges.dedup();
+        ranges
+    }
+
+    pub fn image_desc(&self, Res(res): Res) -> Option<&ImageDesc> {
+        match &self.graph[res] {
+            NodeId::Resource(ResourceTy::Image(_, desc)) => Some(desc),
+            _ => None,
+        }
+    }
+
+    pub fn buffer_size(&self, Res(res): Res) -> Option<vk::DeviceSize> {
+        match self.graph[res] {
+            NodeId::Resource(ResourceTy::Buffer(_, size)) => Some(size),
+            _ => None,
+        }
+    }
+
+    /// Every usage the image needs to be created with, according to the passes using it.
+    pub fn image_usage(&self, res: Res) -> vk::ImageUsageFlags {
+        let mut usage = vk::ImageUsageFlags::empty();
+
+        for (_, edge) in self.resource_edges(res) {
+            usage |= match *edge {
+                EdgeAction::Read(flags) => image_read_usage(self.effective_read_flags(flags)),
+                EdgeAction::Write(flags, _) => image_write_usage(flags),
+            };
+        }
+
+        usage
+    }
+
+    /// Every usage the buffer needs to be created with, according to the passes using it.
+    pub fn buffer_usage(&self, res: Res) -> vk::BufferUsageFlags {
+        let mut usage = vk::BufferUsageFlags::empty();
+
+        for (pass, edge) in self.resource_edges(res) {
+            usage |= match *edge {
+                EdgeAction::Read(flags) => buffer_read_usage(pass, flags),
+                EdgeAction::Write(flags, _) => buffer_write_usage(pass, flags),
+            };
+        }
+
+        usage
+    }
+
+    /// The edges touching a resource, along with the action at their other end.
+    fn resource_edges(&self, Res(res): Res) -> impl Iterator<Item = (&ActionTy<C>, &EdgeAction)> {
+        let action = move |node| match &self.graph[node] {
+            NodeId::Action(action) => action,
+            _ => unreachable!("Resources are only connected to actions"),
+        };
+
+        self.graph
+            .edges_directed(res, Direction::Incoming)
+            .map(move |edge| (action(edge.source()), edge.weight()))
+            .chain(
+                self.graph
+                    .edges_directed(res, Direction::Outgoing)
+                    .map(move |edge| (action(edge.target()), edge.weight())),
+            )
+    }
+}
+
+fn image_read_usage(flags: ReadActionFlags) -> vk::ImageUsageFlags {
+    let mut usage = vk::ImageUsageFlags::empty();
+    if flags.contains(ReadActionFlags::INPUT_ATTACHMENT) {
+        usage |= vk::ImageUsageFlags::INPUT_ATTACHMENT;
+    }
+    if flags.contains(ReadActionFlags::DEPTH_ATTACHMENT) {
+        usage |= vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+    }
+    if flags.contains(ReadActionFlags::SAMPLED) {
+        usage |= vk::ImageUsageFlags::SAMPLED;
+    }
+    if flags.contains(ReadActionFlags::TRANSFER) {
+        usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+    }
+    if flags.contains(ReadActionFlags::STORAGE) {
+        usage |= vk::ImageUsageFlags::STORAGE;
+    }
+    usage
+}
+
+fn image_write_usage(flags: WriteActionFlags) -> vk::ImageUsageFlags {
+    let mut usage = vk::ImageUsageFlags::empty();
+    if flags.contains(WriteActionFlags::COLOR_ATTACHMENT) {
+        usage |= vk::ImageUsageFlags::COLOR_ATTACHMENT;
+    }
+    if flags.contains(WriteActionFlags::DEPTH_ATTACHMENT) {
+        usage |= vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+    }
+    if flags.contains(WriteActionFlags::TRANSFER) {
+        usage |= vk::ImageUsageFlags::TRANSFER_DST;
+    }
+    if flags.contains(WriteActionFlags::STORAGE) {
+        usage |= vk::ImageUsageFlags::STORAGE;
+    }
+    usage
+}
+
+fn buffer_read_usage<C>(pass: &ActionTy<C>, flags: ReadActionFlags) -> vk::BufferUsageFlags {
+    let mut usage = vk::BufferUsageFlags::empty();
+    if flags.is_empty() {
+        // Plain bound buffer
+        usage |= match pass {
+            ActionTy::Raster(_) => {
+                vk::BufferUsageFlags::VERTEX_BUFFER
+                    | vk::BufferUsageFlags::INDEX_BUFFER
+                    | vk::BufferUsageFlags::UNIFORM_BUFFER
+            }
+            ActionTy::Compute(_) | ActionTy::RayTracing(_) => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            ActionTy::AccelerationStructureBuild(_) => {
+                vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+            }
+            ActionTy::External | ActionTy::Transfer(_) => vk::BufferUsageFlags::TRANSFER_SRC,
+        };
+    }
+    if flags.contains(ReadActionFlags::SAMPLED) {
+        usage |= vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER;
+    }
+    if flags.contains(ReadActionFlags::TRANSFER) {
+        usage |= vk::BufferUsageFlags::TRANSFER_SRC;
+    }
+    if flags.contains(ReadActionFlags::STORAGE) {
+        usage |= vk::BufferUsageFlags::STORAGE_BUFFER;
+    }
+    if flags.contains(ReadActionFlags::ACCELERATION_STRUCTURE) {
+        usage |= vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR;
+    }
+    usage
+}
+
+fn buffer_write_usage<C>(pass: &ActionTy<C>, flags: WriteActionFlags) -> vk::BufferUsageFlags {
+    let mut usage = vk::BufferUsageFlags::empty();
+    if flags.contains(WriteActionFlags::TRANSFER) {
+        usage |= vk::BufferUsageFlags::TRANSFER_DST;
+    }
+    if flags.contains(WriteActionFlags::STORAGE) {
+        usage |= vk::BufferUsageFlags::STORAGE_BUFFER;
+        if let ActionTy::AccelerationStructureBuild(_) = pass {
+            // Scratch buffers are passed by address
+            usage |= vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
+        }
+    }
+    if flags.contains(WriteActionFlags::ACCELERATION_STRUCTURE) {
+        usage |= vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR;
+    }
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use crate::tests::{Graph, IMAGE};
+
+    #[test]
+    fn usage_is_derived_from_passes() {
+        let mut graph = Graph::new();
+
+        let gbuffer = graph.register_resource_image("GBuffer", IMAGE);
+        let output = graph.register_resource_image("Output", IMAGE);
+        let vertices = graph.register_resource_buffer("Vertices", 1024);
+        let histogram = graph.register_resource_buffer("Histogram", 256);
+
+        graph.copy_from_host(vertices);
+        graph
+            .create_raster_pass("Geometry")
+            .add_bound_buffer(vertices)
+            .add_color_attachment(gbuffer);
+        graph
+            .create_compute_pass("Histogram")
+            .add_input_storage_buffer(gbuffer)
+            .add_output_storage_buffer(histogram);
+        graph
+            .create_raster_pass("Post")
+            .add_sampled(gbuffer)
+            .add_color_attachment(output);
+        graph.copy_to_host(output);
+
+        assert_eq!(
+            graph.image_usage(gbuffer),
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::SAMPLED
+        );
+        assert_eq!(
+            graph.image_usage(output),
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC
+        );
+        assert!(graph
+            .buffer_usage(vertices)
+            .contains(vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER));
+        assert_eq!(
+            graph.buffer_usage(histogram),
+            vk::BufferUsageFlags::STORAGE_BUFFER
+        );
+        assert_eq!(graph.buffer_size(histogram), Some(256));
+        assert_eq!(graph.image_desc(gbuffer), Some(&IMAGE));
+    }
+}
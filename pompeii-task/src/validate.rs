@@ -0,0 +1,333 @@
+use std::{
+    collections::HashSet,
+    fmt::{Debug, Display, Formatter},
+};
+
+use petgraph::{prelude::NodeIndex, visit::EdgeRef, Direction};
+
+use crate::{
+    Action, EdgeAction, NodeId, ReadActionFlags, Res, ResourceTy, TaskGraph, WriteActionFlags,
+};
+
+/// A mistake found in a [TaskGraph] by [TaskGraph::validate].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic<'graph, R, C> {
+    /// The pass reads a resource that nothing ever writes
+    ReadWithoutWriter {
+        pass: (Action, &'graph C),
+        resource: (Res, &'graph R),
+    },
+    /// Both passes write the resource but nothing decides which one goes first
+    UnorderedWrites {
+        passes: [(Action, &'graph C); 2],
+        resource: (Res, &'graph R),
+    },
+    /// The pass uses the same image as its depth attachment and as a sampled image
+    SampledDepthAttachment {
+        pass: (Action, &'graph C),
+        resource: (Res, &'graph R),
+    },
+    /// The pass uses a buffer as an attachment
+    ImageOnlyUsageOnBuffer {
+        pass: (Action, &'graph C),
+        resource: (Res, &'graph R),
+    },
+}
+
+impl<R: Debug, C: Debug> Display for Diagnostic<'_, R, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::ReadWithoutWriter {
+                pass: (_, pass),
+                resource: (_, res),
+            } => write!(f, "Pass {:?} reads {:?} which is never written", pass, res),
+            Diagnostic::UnorderedWrites {
+                passes: [(_, first), (_, second)],
+                resource: (_, res),
+            } => write!(
+                f,
+                "Passes {:?} and {:?} both write {:?} without any ordering between them",
+                first, second, res
+            ),
+            Diagnostic::SampledDepthAttachment {
+                pass: (_, pass),
+                resource: (_, res),
+            } => write!(
+                f,
+                "Pass {:?} samples {:?} while using it as its depth attachment",
+                pass, res
+            ),
+            Diagnostic::ImageOnlyUsageOnBuffer {
+                pass: (_, pass),
+                resource: (_, res),
+            } => write!(f, "Pass {:?} uses buffer {:?} as an attachment", pass, res),
+        }
+    }
+}
+
+impl<R: Debug, C: Debug> TaskGraph<R, C> {
+    /// Look for mistakes in the graph, an empty list means everything looks fine.
+    pub fn validate(&self) -> Vec<Diagnostic<'_, R, C>> {
+        let mut diagnostics = Vec::new();
+
+        for pass in self.pass_nodes() {
+            let mut edges = self
+                .graph
+                .edges_directed(pass, Direction::Incoming)
+                .chain(self.graph.edges_directed(pass, Direction::Outgoing))
+                .collect::<Vec<_>>();
+            edges.sort_by_key(|edge| edge.id());
+
+            let mut depth_attachments = HashSet::new();
+            let mut sampled = HashSet::new();
+
+            for edge in edges {
+                let (resource, is_buffer_misuse) = match *edge.weight() {
+                    EdgeAction::Read(flags) => {
+                        let resource = edge.source();
+                        if !self.imports.contains_key(&resource)
+                            && !self.is_history_previous(Res(resource))
+                            && self
+                                .graph
+                                .edges_directed(resource, Direction::Incoming)
+                                .next()
+                                .is_none()
+                        {
+                            diagnostics.push(Diagnostic::ReadWithoutWriter {
+                                pass: self.pass_diag(pass),
+                                resource: self.resource_diag(resource),
+                            });
+                        }
+                        if flags.contains(ReadActionFlags::SAMPLED) {
+                            sampled.insert(resource);
+                        }
+                        if flags.contains(ReadActionFlags::DEPTH_ATTACHMENT) {
+                            depth_attachments.insert(resource);
+                        }
+
+                        let attachment =
+                            ReadActionFlags::INPUT_ATTACHMENT | ReadActionFlags::DEPTH_ATTACHMENT;
+                        (resource, flags.intersects(attachment))
+                    }
+                    EdgeAction::Write(flags, _) => {
+                        let resource = edge.target();
+                        if flags.contains(WriteActionFlags::DEPTH_ATTACHMENT) {
+                            depth_attachments.insert(resource);
+                        }
+
+                        let attachment =
+                            WriteActionFlags::COLOR_ATTACHMENT | WriteActionFlags::DEPTH_ATTACHMENT;
+                        (resource, flags.intersects(attachment))
+                    }
+                };
+
+                if is_buffer_misuse
+                    && matches!(
+                        self.graph[resource],
+                        NodeId::Resource(ResourceTy::Buffer(..))
+                    )
+                {
+                    diagnostics.push(Diagnostic::ImageOnlyUsageOnBuffer {
+                        pass: self.pass_diag(pass),
+                        resource: self.resource_diag(resource),
+                    });
+                }
+            }
+
+            let mut both = depth_attachments
+                .intersection(&sampled)
+                .copied()
+                .collect::<Vec<_>>();
+            both.sort_unstable();
+            for resource in both {
+                diagnostics.push(Diagnostic::SampledDepthAttachment {
+                    pass: self.pass_diag(pass),
+                    resource: self.resource_diag(resource),
+                });
+            }
+        }
+
+        diagnostics.extend(self.unordered_writes());
+        diagnostics
+    }
+
+    fn unordered_writes(&self) -> Vec<Diagnostic<'_, R, C>> {
+        self.unordered_write_pairs()
+            .into_iter()
+            .map(|(first, second, resource)| Diagnostic::UnorderedWrites {
+                passes: [self.pass_diag(first), self.pass_diag(second)],
+                resource: self.resource_diag(resource),
+            })
+            .collect()
+    }
+
+    fn pass_diag(&self, pass: NodeIndex) -> (Action, &C) {
+        (Action(pass), self.pass_tag(Action(pass)))
+    }
+
+    fn resource_diag(&self, resource: NodeIndex) -> (Res, &R) {
+        (Res(resource), self.resource_tag(Res(resource)))
+    }
+}
+
+impl<R, C> TaskGraph<R, C> {
+    /// Passes writing a common part of a resource with nothing deciding which one goes first,
+    /// as `(first declared, second declared, resource)`.
+    pub(crate) fn unordered_write_pairs(&self) -> Vec<(NodeIndex, NodeIndex, NodeIndex)> {
+        let mut pairs = Vec::new();
+
+        for resource in self
+            .graph
+            .node_indices()
+            .filter(|&node| matches!(self.graph[node], NodeId::Resource(_)))
+        {
+            let mut writers = self.writers(resource).collect::<Vec<_>>();
+            writers.sort_unstable();
+            writers.dedup();
+
+            for (i, &first) in writers.iter().enumerate() {
+                for &second in &writers[i + 1..] {
+                    if self.writes_overlap(first, second, resource)
+                        && !self.depends_on(second, first)
+                        && !self.depends_on(first, second)
+                    {
+                        pairs.push((first, second, resource));
+                    }
+                }
+            }
+        }
+
+        pairs
+    }
+
+    /// Whether both passes write some common part of the resource.
+    fn writes_overlap(&self, first: NodeIndex, second: NodeIndex, resource: NodeIndex) -> bool {
+        let ranges = |pass| {
+            self.graph
+                .edges_connecting(pass, resource)
+                .map(|edge| self.edge_range(edge.id(), Res(resource)))
+                .collect::<Vec<_>>()
+        };
+
+        let second = ranges(second);
+        ranges(first)
+            .iter()
+            .any(|range| second.iter().any(|other| range.overlaps(other)))
+    }
+
+    /// Whether `pass` transitively depends on `other`.
+    pub(crate) fn depends_on(&self, pass: NodeIndex, other: NodeIndex) -> bool {
+        let mut visited = HashSet::new();
+        let mut to_visit = self.pass_dependencies(pass);
+
+        while let Some(dep) = to_visit.pop() {
+            if dep == other {
+                return true;
+            }
+            if visited.insert(dep) {
+                to_visit.extend(self.pass_dependencies(dep));
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{
+        tests::{compile, Graph, IMAGE},
+        Diagnostic, ImageDesc, SubresourceRange,
+    };
+
+    #[test]
+    fn valid_graph_has_no_diagnostics() {
+        let mut graph = Graph::new();
+
+        let texture = graph.register_resource_image("Texture", IMAGE);
+        let color = graph.register_resource_image("Color", IMAGE);
+
+        graph.copy_from_host(texture);
+        graph
+            .create_raster_pass("Main")
+            .add_sampled(texture)
+            .add_color_attachment(color);
+        graph.copy_to_host(color);
+
+        assert!(graph.validate().is_empty());
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        let mut graph = Graph::new();
+
+        let never_written = graph.register_resource_image("Never written", IMAGE);
+        let depth = graph.register_resource_image("Depth", IMAGE);
+        let color = graph.register_resource_image("Color", IMAGE);
+        let buffer = graph.register_resource_buffer("Buffer", 1024);
+
+        let first = graph
+            .create_raster_pass("First")
+            .add_sampled(never_written)
+            .add_depth_attachment(depth, false)
+            .add_sampled(depth)
+            .add_color_attachment(color)
+            .id();
+        let second = graph
+            .create_raster_pass("Second")
+            .add_color_attachment(color)
+            .add_color_attachment(buffer)
+            .id();
+
+        let diagnostics = graph.validate();
+
+        assert_eq!(
+            diagnostics,
+            [
+                Diagnostic::ReadWithoutWriter {
+                    pass: (first, &"First"),
+                    resource: (never_written, &"Never written"),
+                },
+                Diagnostic::SampledDepthAttachment {
+                    pass: (first, &"First"),
+                    resource: (depth, &"Depth"),
+                },
+                Diagnostic::ImageOnlyUsageOnBuffer {
+                    pass: (second, &"Second"),
+                    resource: (buffer, &"Buffer"),
+                },
+                Diagnostic::UnorderedWrites {
+                    passes: [(first, &"First"), (second, &"Second")],
+                    resource: (color, &"Color"),
+                },
+            ]
+        );
+        assert_eq!(
+            diagnostics[3].to_string(),
+            "Passes \"First\" and \"Second\" both write \"Color\" without any ordering between them"
+        );
+    }
+
+    #[test]
+    fn writes_to_different_layers_are_not_unordered() {
+        let mut graph = Graph::new();
+
+        let cubemap = graph.register_resource_image(
+            "Cubemap",
+            ImageDesc {
+                array_layers: 6,
+                ..IMAGE
+            },
+        );
+        for face in 0..6 {
+            graph
+                .create_raster_pass("Face")
+                .add_color_attachment_range(cubemap, SubresourceRange::layer(face));
+        }
+        graph.copy_to_host(cubemap);
+
+        assert!(graph.validate().is_empty());
+        assert_eq!(compile(&graph).passes().len(), 6);
+    }
+}
@@ -1,47 +1,54 @@
-use std::slice::from_ref;
+use std::{slice::from_ref, sync::Arc};
 
 use ash::vk;
-use log::debug;
 
 use crate::{
     alloc::VkBufferHandle,
+    deletion::{Deletion, DeletionQueue},
     errors::Result,
     mesh::{Mesh, MeshIndex, MeshVertex, VertexPosNormUvF32},
     PompeiiRenderer,
 };
 
-#[derive(Debug, Clone)]
-pub(crate) struct AsData {
+/// An acceleration structure owning its buffer, destroyed once the GPU is done with the frames
+/// that may use it.
+#[derive(Debug)]
+pub struct AccelerationStructure {
     pub(crate) handle: vk::AccelerationStructureKHR,
     pub(crate) buffer: VkBufferHandle,
+    deletion: Arc<DeletionQueue>,
 }
 
-impl AsData {
-    pub(crate) fn destroy_on_exit(&self, renderer: &PompeiiRenderer) {
-        let me = self.clone();
-        renderer
-            .alloc_deletion_queue
-            .lock()
-            .push(Box::new(move |(_, ext_as), vma| unsafe {
-                let me = me;
-                debug!("Destroy AS");
-                ext_as.destroy_acceleration_structure(me.handle, None);
-                me.buffer.destroy(vma);
-                Ok(())
-            }))
+impl AccelerationStructure {
+    fn new(
+        renderer: &PompeiiRenderer,
+        handle: vk::AccelerationStructureKHR,
+        buffer: VkBufferHandle,
+    ) -> Self {
+        Self {
+            handle,
+            buffer,
+            deletion: Arc::clone(&renderer.deletion_queue),
+        }
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Blas(pub(crate) AsData);
+    pub fn handle(&self) -> vk::AccelerationStructureKHR {
+        self.handle
+    }
+}
 
-impl Blas {
-    #[inline]
-    pub fn destroy_on_exit(&self, renderer: &PompeiiRenderer) {
-        self.0.destroy_on_exit(renderer);
+impl Drop for AccelerationStructure {
+    fn drop(&mut self) {
+        self.deletion.push(Deletion::AccelerationStructure(
+            self.handle,
+            self.buffer.clone(),
+        ));
     }
 }
 
+#[derive(Debug)]
+pub struct Blas(pub(crate) AccelerationStructure);
+
 #[derive(Debug, Default)]
 pub struct BlasInput {
     geometries: Vec<vk::AccelerationStructureGeometryKHR>,
@@ -204,7 +211,7 @@ impl PompeiiRenderer {
             .into_iter()
             .map(|i| {
                 let (handle, buffer) = i.accel.unwrap();
-                Blas(AsData { handle, buffer })
+                Blas(AccelerationStructure::new(self, handle, buffer))
             })
             .collect())
     }
@@ -239,15 +246,8 @@ impl PompeiiRenderer {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Tlas(pub(crate) AsData);
-
-impl Tlas {
-    #[inline]
-    pub fn destroy_on_exit(&self, renderer: &PompeiiRenderer) {
-        self.0.destroy_on_exit(renderer);
-    }
-}
+#[derive(Debug)]
+pub struct Tlas(pub(crate) AccelerationStructure);
 
 impl PompeiiRenderer {
     pub fn create_tlas<'a>(&self, blases: impl Iterator<Item = &'a Blas>) -> Result<Tlas> {
@@ -374,12 +374,12 @@ impl PompeiiRenderer {
         unsafe {
             self.submit_and_wait(compute.queue, cmds, &[], &[], &[])?;
             self.free_buffer(scratch_buffer);
-            self.free_buffer(instance_buffer);
         }
 
-        Ok(Tlas(AsData {
-            buffer: tlas_buffer,
-            handle: tlas_handle,
-        }))
+        Ok(Tlas(AccelerationStructure::new(
+            self,
+            tlas_handle,
+            tlas_buffer,
+        )))
     }
 }
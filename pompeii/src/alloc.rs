@@ -1,9 +1,33 @@
-use std::{ffi::CString, ptr, slice::from_ref, sync::Arc};
+use std::{
+    ffi::CString,
+    future::Future,
+    marker::PhantomData,
+    ops::Deref,
+    pin::Pin,
+    ptr,
+    slice::from_ref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Waker},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
 
 use ash::vk;
 use log::trace;
+use parking_lot::{Condvar, Mutex};
+use vk_sync_fork::{AccessType, ImageLayout};
 
-use crate::{errors::Result, mesh::VertexPosNormUvF32, PompeiiRenderer};
+use crate::{
+    deletion::{Deletion, DeletionQueue},
+    errors::{PompeiiError, Result},
+    images::{create_image_view, format_aspect, texel_size},
+    memory::MemoryCategory,
+    mesh::VertexPosNormUvF32,
+    PompeiiRenderer,
+};
 
 #[derive(Debug)]
 pub(crate) struct VmaPools {
@@ -18,59 +42,115 @@ pub struct BufferHandle(usize);
 
 pub struct PompeiiTransferContext<'a> {
     renderer: &'a PompeiiRenderer,
-    vma: Arc<vk_mem::Allocator>,
+    /// Shared with its ticket, to recognize the readbacks of this context
+    id: u64,
     ops_buffer_copy: Vec<(vk::Buffer, vk::Buffer, vk::BufferCopy)>,
-    // ops_image_copy: Vec<vk::ImageCopy>,
+    ops_image_copy: Vec<ImageUpload>,
+    ops_buffer_read: Vec<(vk::Buffer, vk::Buffer, vk::BufferCopy)>,
+    ops_image_read: Vec<ImageRead>,
     to_destroy: Vec<VkBufferHandle>,
+    readbacks: Vec<VkBufferHandle>,
+}
+
+/// A staging buffer copied to the base level of an image.
+struct ImageUpload {
+    from: vk::Buffer,
+    to: vk::Image,
+    extent: vk::Extent3D,
+    range: vk::ImageSubresourceRange,
+    /// Blit the base level down to the others once copied
+    generate_mips: bool,
+}
+
+/// A level of a sampled image copied to a readback buffer.
+struct ImageRead {
+    from: vk::Image,
+    to: vk::Buffer,
+    extent: vk::Extent3D,
+    range: vk::ImageSubresourceRange,
+}
+
+/// Data copied to the host by a [PompeiiTransferContext], to get from its [TransferTicket].
+#[derive(Debug)]
+pub struct Readback<T> {
+    ticket: u64,
+    index: usize,
+    len: usize,
+    _marker: PhantomData<T>,
 }
 
 impl PompeiiRenderer {
     pub fn start_transfer_operations(&self) -> PompeiiTransferContext {
-        let vma = Arc::clone(&self.vma);
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
         PompeiiTransferContext {
             renderer: self,
-            vma,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
             ops_buffer_copy: Vec::new(),
-            // ops_image_copy: Default::default(),
+            ops_image_copy: Vec::new(),
+            ops_buffer_read: Vec::new(),
+            ops_image_read: Vec::new(),
             to_destroy: Vec::new(),
+            readbacks: Vec::new(),
         }
     }
 
-    pub unsafe fn free_buffer_on_exit(&self, buffer: VkBufferHandle) {
-        self.alloc_deletion_queue
-            .lock()
-            .push(Box::new(move |_, vma| {
-                let buffer = buffer;
-                vma.destroy_buffer(buffer.handle, buffer.allocation);
-                Ok(())
-            }));
-    }
-
-    pub unsafe fn free_buffer(&self, buffer: VkBufferHandle) {
+    pub(crate) unsafe fn free_buffer(&self, buffer: VkBufferHandle) {
+        if let Some(category) = buffer.category {
+            self.memory_counters.untrack(category);
+        }
         self.vma.destroy_buffer(buffer.handle, buffer.allocation);
     }
 }
 
 impl<'a> PompeiiTransferContext<'a> {
-    pub fn create_vertex_buffer(
+    /// Upload `data` to a new device local buffer, `TRANSFER_DST` is added to `usage`. It is
+    /// counted under `category` in the [memory report](PompeiiRenderer::memory_report).
+    ///
+    /// `name` is the debug name of the buffer, followed by its size.
+    pub fn create_buffer_with_data<T: Copy>(
         &mut self,
-        vertices: &[VertexPosNormUvF32],
-    ) -> Result<VkBufferHandle> {
-        let size = (vertices.len() * std::mem::size_of::<VertexPosNormUvF32>()) as _;
-        let staging = self.renderer.alloc_staging_buffer(size)?;
-        let vertex_buffer = self.renderer.alloc_vertex_buffer(size)?;
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+        name: &str,
+        category: MemoryCategory,
+    ) -> Result<Buffer> {
+        self.upload_buffer(data, usage, name, category)
+    }
+
+    fn upload_buffer<T: Copy>(
+        &mut self,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+        name: &str,
+        category: MemoryCategory,
+    ) -> Result<Buffer> {
+        let size = std::mem::size_of_val(data) as _;
+        // Deleted when dropped if anything fails before the copy is recorded
+        let buffer = unsafe {
+            self.renderer.create_buffer(
+                size,
+                usage | vk::BufferUsageFlags::TRANSFER_DST,
+                vk_mem::MemoryUsage::GpuOnly,
+            )?
+        };
+        let buffer = Buffer::new(self.renderer, self.renderer.track_buffer(buffer, category));
 
         self.renderer.debug_utils.name_buffer(
             &self.renderer.device,
-            vertex_buffer.handle,
-            &CString::new(format!("Vertex Buffer (size: {})", size)).unwrap(),
+            buffer.handle,
+            &CString::new(format!("{} (size: {})", name, size)).unwrap(),
         )?;
 
-        unsafe { self.renderer.store_to_buffer(&staging, vertices)? };
+        let staging = self.renderer.alloc_staging_buffer(size)?;
+        if let Err(err) = unsafe { self.renderer.store_to_buffer(&staging, data) } {
+            unsafe { self.renderer.free_buffer(staging) };
+            return Err(err);
+        }
 
         self.ops_buffer_copy.push((
             staging.handle,
-            vertex_buffer.handle,
+            buffer.handle,
             vk::BufferCopy::builder()
                 .size(size)
                 .src_offset(0)
@@ -80,107 +160,769 @@ impl<'a> PompeiiTransferContext<'a> {
 
         self.to_destroy.push(staging);
 
-        Ok(vertex_buffer)
+        Ok(buffer)
     }
 
-    pub fn create_index_buffer(&mut self, indices: &[u16]) -> Result<VkBufferHandle> {
-        let size = (indices.len() * std::mem::size_of::<u16>()) as _;
-        let staging = self.renderer.alloc_staging_buffer(size)?;
-        let index_buffer = self.renderer.alloc_index_buffer(size)?;
+    pub fn create_vertex_buffer(&mut self, vertices: &[VertexPosNormUvF32]) -> Result<Buffer> {
+        self.upload_buffer(
+            vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+            "Vertex Buffer",
+            MemoryCategory::Vertex,
+        )
+    }
 
-        self.renderer.debug_utils.name_buffer(
-            &self.renderer.device,
-            index_buffer.handle,
-            &CString::new(format!("Index Buffer (size: {})", size)).unwrap(),
-        )?;
+    pub fn create_index_buffer(&mut self, indices: &[u16]) -> Result<Buffer> {
+        self.upload_buffer(
+            indices,
+            vk::BufferUsageFlags::INDEX_BUFFER
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+            "Index Buffer",
+            MemoryCategory::Index,
+        )
+    }
 
-        unsafe { self.renderer.store_to_buffer(&staging, indices)? };
+    pub fn create_acceleration_structure_instance_buffer(
+        &mut self,
+        instances: &[vk::AccelerationStructureInstanceKHR],
+    ) -> Result<Buffer> {
+        self.upload_buffer(
+            instances,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            "TLAS Instances buffer",
+            MemoryCategory::AccelerationStructure,
+        )
+    }
 
-        self.ops_buffer_copy.push((
-            staging.handle,
-            index_buffer.handle,
-            vk::BufferCopy::builder()
-                .size(size)
-                .src_offset(0)
-                .dst_offset(0)
-                .build(),
-        ));
+    /// Upload the base level of a new sampled 2D texture, which is left in
+    /// `SHADER_READ_ONLY_OPTIMAL`. The other levels are allocated but their content is undefined.
+    ///
+    /// `data` must hold exactly one texel of `format` per pixel of the base level, and
+    /// `mip_levels` can't be 0.
+    pub fn create_texture_2d<T: Copy>(
+        &mut self,
+        data: &[T],
+        extent: vk::Extent2D,
+        format: vk::Format,
+        mip_levels: u32,
+    ) -> Result<Image> {
+        if mip_levels == 0 {
+            return Err(PompeiiError::NoMipLevels);
+        }
+        self.upload_texture_2d(data, extent, format, mip_levels, false)
+    }
 
-        self.to_destroy.push(staging);
+    /// Upload the base level of a new sampled 2D texture and generate its full mip chain on the
+    /// GPU, by blitting each level down to the next one.
+    ///
+    /// Fails if the format can't be blitted with linear filtering. The whole submission runs on
+    /// the graphics queue if the transfer queue can't blit.
+    pub fn create_texture_2d_with_mips<T: Copy>(
+        &mut self,
+        data: &[T],
+        extent: vk::Extent2D,
+        format: vk::Format,
+    ) -> Result<Image> {
+        if !self.renderer.supports_linear_blit(format) {
+            return Err(PompeiiError::NoLinearBlit(format));
+        }
 
-        Ok(index_buffer)
+        let mip_levels = u32::BITS - extent.width.max(extent.height).max(1).leading_zeros();
+        self.upload_texture_2d(data, extent, format, mip_levels, true)
     }
 
-    pub fn create_acceleration_structure_instance_buffer(
+    fn upload_texture_2d<T: Copy>(
         &mut self,
-        instances: &[vk::AccelerationStructureInstanceKHR],
-    ) -> Result<VkBufferHandle> {
-        let size =
-            (instances.len() * std::mem::size_of::<vk::AccelerationStructureInstanceKHR>()) as _;
+        data: &[T],
+        extent: vk::Extent2D,
+        format: vk::Format,
+        mip_levels: u32,
+        generate_mips: bool,
+    ) -> Result<Image> {
+        // Blitted from for mips, and copied from when read back
+        let usage = vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST
+            | vk::ImageUsageFlags::SAMPLED;
+
+        let texel_size =
+            texel_size(format).ok_or(PompeiiError::UnsupportedTextureFormat(format))?;
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        let expected =
+            extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * texel_size;
+        if size != expected {
+            return Err(PompeiiError::TextureDataSize {
+                expected,
+                actual: size,
+            });
+        }
+
         let staging = self.renderer.alloc_staging_buffer(size)?;
-        let instances_buffer = self
+        let image = self
             .renderer
-            .alloc_acceleration_structure_instance_buffer(size)?;
+            .alloc_texture_image(extent, format, mip_levels, usage)?;
 
-        self.renderer.debug_utils.name_buffer(
+        self.renderer.debug_utils.name_image(
             &self.renderer.device,
-            instances_buffer.handle,
-            &CString::new(format!("TLAS Instances buffer (size: {})", size)).unwrap(),
+            image.handle,
+            &CString::new(format!(
+                "Texture ({}x{}, {:?})",
+                extent.width, extent.height, format
+            ))
+            .unwrap(),
         )?;
 
-        unsafe {
-            self.renderer.store_to_buffer(&staging, instances)?;
+        unsafe { self.renderer.store_to_buffer(&staging, data)? };
+
+        self.ops_image_copy.push(ImageUpload {
+            from: staging.handle,
+            to: image.handle,
+            extent: image.extent,
+            range: image.full_range(),
+            generate_mips: generate_mips && mip_levels > 1,
+        });
+
+        self.to_destroy.push(staging);
+
+        Ok(image)
+    }
+
+    /// Copy `len` elements of `buffer` from `offset` bytes back to the host, after the uploads of
+    /// this context. `TRANSFER_SRC` must be in the usage of the buffer, and the elements must fit
+    /// in it.
+    ///
+    /// Whatever wrote the buffer before must already be complete and visible to transfers.
+    pub fn read_buffer<T: Copy>(
+        &mut self,
+        buffer: &VkBufferHandle,
+        offset: vk::DeviceSize,
+        len: usize,
+    ) -> Result<Readback<T>> {
+        let size = (std::mem::size_of::<T>() * len) as vk::DeviceSize;
+        if offset
+            .checked_add(size)
+            .filter(|&end| end <= buffer.size)
+            .is_none()
+        {
+            return Err(PompeiiError::ReadOutOfBounds {
+                offset,
+                size,
+                buffer_size: buffer.size,
+            });
         }
+        let readback = self.renderer.alloc_readback_buffer(size)?;
 
-        self.ops_buffer_copy.push((
-            staging.handle,
-            instances_buffer.handle,
+        self.ops_buffer_read.push((
+            buffer.handle,
+            readback.handle,
             vk::BufferCopy::builder()
                 .size(size)
-                .src_offset(0)
+                .src_offset(offset)
                 .dst_offset(0)
                 .build(),
         ));
 
-        self.to_destroy.push(staging);
+        Ok(self.push_readback(readback, len))
+    }
+
+    /// Copy a mip level of a texture back to the host after the uploads of this context, as
+    /// tightly packed texels. The size of `T` must divide the texel size of the format.
+    ///
+    /// The image must be in `SHADER_READ_ONLY_OPTIMAL`, as left by the uploads, and is put back
+    /// in it.
+    pub fn read_image<T: Copy>(&mut self, image: &Image, mip_level: u32) -> Result<Readback<T>> {
+        let extent = vk::Extent3D {
+            width: (image.extent.width >> mip_level).max(1),
+            height: (image.extent.height >> mip_level).max(1),
+            depth: 1,
+        };
+        let texel_size =
+            texel_size(image.format).ok_or(PompeiiError::UnsupportedTextureFormat(image.format))?;
+        let element_size = std::mem::size_of::<T>() as vk::DeviceSize;
+        if element_size == 0 || texel_size % element_size != 0 {
+            return Err(PompeiiError::ReadbackElementSize {
+                format: image.format,
+                size: element_size,
+            });
+        }
 
-        Ok(instances_buffer)
+        let size = extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * texel_size;
+        let len = (size / element_size) as usize;
+        let readback = self.renderer.alloc_readback_buffer(size)?;
+
+        self.ops_image_read.push(ImageRead {
+            from: image.handle,
+            to: readback.handle,
+            extent,
+            range: vk::ImageSubresourceRange {
+                base_mip_level: mip_level,
+                level_count: 1,
+                ..image.full_range()
+            },
+        });
+
+        Ok(self.push_readback(readback, len))
     }
 
-    pub fn submit_and_wait(self) -> Result<()> {
-        let device = &self.renderer.device;
-        let queue = self.renderer.queues.transfer();
+    fn push_readback<T>(&mut self, readback: VkBufferHandle, len: usize) -> Readback<T> {
+        self.readbacks.push(readback);
+        Readback {
+            ticket: self.id,
+            index: self.readbacks.len() - 1,
+            len,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Submit the recorded transfers without waiting for them, the staging buffers are released
+    /// once the returned ticket sees them complete.
+    pub fn submit(self) -> Result<TransferTicket<'a>> {
+        let renderer = self.renderer;
+        let device = &renderer.device;
+        let blits = self
+            .ops_image_copy
+            .iter()
+            .any(|upload| upload.generate_mips);
+        let queue_index = if blits && !renderer.transfer_queue_can_blit() {
+            renderer.queues.graphics_index
+        } else {
+            renderer.queues.transfer_index
+        };
 
         unsafe {
-            let cmd = self
-                .renderer
-                .record_one_time_command_buffer(queue.pool, |cmd| {
-                    for (from, to, op) in &self.ops_buffer_copy {
-                        device.cmd_copy_buffer(cmd, *from, *to, from_ref(op));
-                    }
-                    Ok(())
-                })?;
-
-            let fence = device.create_fence(&vk::FenceCreateInfo::builder(), None)?;
+            let semaphore = device.create_semaphore(
+                &vk::SemaphoreCreateInfo::builder().push_next(
+                    &mut vk::SemaphoreTypeCreateInfo::builder()
+                        .semaphore_type(vk::SemaphoreType::TIMELINE)
+                        .initial_value(0),
+                ),
+                None,
+            )?;
+
+            let queue = renderer.queues.by_index(queue_index);
+            let cmd = renderer.record_one_time_command_buffer(queue.pool, |cmd| {
+                for (from, to, op) in &self.ops_buffer_copy {
+                    device.cmd_copy_buffer(cmd, *from, *to, from_ref(op));
+                }
+                for upload in &self.ops_image_copy {
+                    self.cmd_upload_image(cmd, upload);
+                }
+
+                if !self.ops_buffer_read.is_empty() || !self.ops_image_read.is_empty() {
+                    // Reads come after the uploads they may read from
+                    device.cmd_pipeline_barrier(
+                        cmd,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::DependencyFlags::empty(),
+                        from_ref(
+                            &vk::MemoryBarrier::builder()
+                                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                                .build(),
+                        ),
+                        &[],
+                        &[],
+                    );
+                }
+                for (from, to, op) in &self.ops_buffer_read {
+                    device.cmd_copy_buffer(cmd, *from, *to, from_ref(op));
+                }
+                for read in &self.ops_image_read {
+                    self.cmd_read_image(cmd, read);
+                }
+                Ok(())
+            })?;
+
             let cmds = [cmd];
-            let info = vk::SubmitInfo::builder().command_buffers(&cmds);
-            device.queue_submit(queue.queue, from_ref(&info), fence)?;
+            let signal_values = [TransferTicket::SIGNAL_VALUE];
+            let mut timeline_info =
+                vk::TimelineSemaphoreSubmitInfo::builder().signal_semaphore_values(&signal_values);
+            let info = vk::SubmitInfo::builder()
+                .command_buffers(&cmds)
+                .signal_semaphores(from_ref(&semaphore))
+                .push_next(&mut timeline_info);
+            device.queue_submit(queue.queue, from_ref(&info), vk::Fence::null())?;
+
+            Ok(TransferTicket {
+                renderer,
+                id: self.id,
+                semaphore,
+                queue_index,
+                cmd,
+                to_destroy: self.to_destroy,
+                readbacks: self.readbacks.into_iter().map(Some).collect(),
+                released: false,
+            })
+        }
+    }
+
+    pub fn submit_and_wait(self) -> Result<()> {
+        self.submit()?.wait()
+    }
+}
+
+impl PompeiiTransferContext<'_> {
+    unsafe fn cmd_upload_image(&self, cmd: vk::CommandBuffer, upload: &ImageUpload) {
+        let device = &self.renderer.device;
+
+        self.renderer.cmd_sync_image_barrier(
+            cmd,
+            &[AccessType::Nothing],
+            &[AccessType::TransferWrite],
+            ImageLayout::Optimal,
+            ImageLayout::Optimal,
+            true,
+            upload.to,
+            upload.range,
+        );
+
+        device.cmd_copy_buffer_to_image(
+            cmd,
+            upload.from,
+            upload.to,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            from_ref(
+                &vk::BufferImageCopy::builder()
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::builder()
+                            .aspect_mask(upload.range.aspect_mask)
+                            .mip_level(0)
+                            .base_array_layer(0)
+                            .layer_count(1)
+                            .build(),
+                    )
+                    .image_extent(upload.extent)
+                    .build(),
+            ),
+        );
+
+        if !upload.generate_mips {
+            self.cmd_shader_read_barrier(
+                cmd,
+                upload.to,
+                upload.range,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+            );
+            return;
+        }
+
+        let level_range = |level: u32| vk::ImageSubresourceRange {
+            base_mip_level: level,
+            level_count: 1,
+            ..upload.range
+        };
+        let level_layers = |level: u32| vk::ImageSubresourceLayers {
+            aspect_mask: upload.range.aspect_mask,
+            mip_level: level,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let level_corner = |level: u32| vk::Offset3D {
+            x: (upload.extent.width >> level).max(1) as i32,
+            y: (upload.extent.height >> level).max(1) as i32,
+            z: 1,
+        };
+
+        let levels = upload.range.level_count;
+        for level in 1..levels {
+            self.renderer.cmd_sync_image_barrier(
+                cmd,
+                &[AccessType::TransferWrite],
+                &[AccessType::TransferRead],
+                ImageLayout::Optimal,
+                ImageLayout::Optimal,
+                false,
+                upload.to,
+                level_range(level - 1),
+            );
+
+            device.cmd_blit_image(
+                cmd,
+                upload.to,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                upload.to,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                from_ref(&vk::ImageBlit {
+                    src_subresource: level_layers(level - 1),
+                    src_offsets: [vk::Offset3D::default(), level_corner(level - 1)],
+                    dst_subresource: level_layers(level),
+                    dst_offsets: [vk::Offset3D::default(), level_corner(level)],
+                }),
+                vk::Filter::LINEAR,
+            );
+        }
+
+        // Every level but the last was blitted from
+        self.cmd_shader_read_barrier(
+            cmd,
+            upload.to,
+            vk::ImageSubresourceRange {
+                level_count: levels - 1,
+                ..upload.range
+            },
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::empty(),
+        );
+        self.cmd_shader_read_barrier(
+            cmd,
+            upload.to,
+            level_range(levels - 1),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+    }
+
+    unsafe fn cmd_read_image(&self, cmd: vk::CommandBuffer, read: &ImageRead) {
+        let device = &self.renderer.device;
+
+        // After the uploads of this submission and their move to the shader read layout, work
+        // from other submissions is waited on with semaphores
+        device.cmd_pipeline_barrier(
+            cmd,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            from_ref(
+                &vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                    .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(read.from)
+                    .subresource_range(read.range)
+                    .build(),
+            ),
+        );
+
+        device.cmd_copy_image_to_buffer(
+            cmd,
+            read.from,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            read.to,
+            from_ref(
+                &vk::BufferImageCopy::builder()
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: read.range.aspect_mask,
+                        mip_level: read.range.base_mip_level,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_extent(read.extent)
+                    .build(),
+            ),
+        );
+
+        self.cmd_shader_read_barrier(
+            cmd,
+            read.from,
+            read.range,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::empty(),
+        );
+    }
+
+    /// Move an uploaded image to `SHADER_READ_ONLY_OPTIMAL`.
+    ///
+    /// The transfer queue may not support the shader stages, the shaders wait for the
+    /// semaphore which makes the writes visible. Textures are shared by the queue families, so
+    /// no ownership transfer is needed. The layout change is only ordered before the readbacks
+    /// of the same submission.
+    unsafe fn cmd_shader_read_barrier(
+        &self,
+        cmd: vk::CommandBuffer,
+        image: vk::Image,
+        range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        src_access_mask: vk::AccessFlags,
+    ) {
+        self.renderer.device.cmd_pipeline_barrier(
+            cmd,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            from_ref(
+                &vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(src_access_mask)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                    .old_layout(old_layout)
+                    .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(image)
+                    .subresource_range(range)
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Submitted transfers, which can be polled or awaited.
+///
+/// The staging buffers and the command buffer are released once the transfers are seen
+/// complete. Dropping the ticket before that doesn't wait, they are released by the next frame
+/// rendered after the transfers complete.
+pub struct TransferTicket<'a> {
+    renderer: &'a PompeiiRenderer,
+    id: u64,
+    semaphore: vk::Semaphore,
+    queue_index: usize,
+    cmd: vk::CommandBuffer,
+    to_destroy: Vec<VkBufferHandle>,
+    readbacks: Vec<Option<VkBufferHandle>>,
+    released: bool,
+}
+
+/// Wakes the tasks awaiting [TransferTicket]s once their transfers are done, from a single
+/// thread polling their semaphores.
+pub(crate) struct TransferWaiter {
+    shared: Arc<WaiterShared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct WaiterShared {
+    state: Mutex<WaiterState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct WaiterState {
+    /// The semaphores of the awaited tickets, with the waker of their latest poll
+    pending: Vec<(vk::Semaphore, Waker)>,
+    stopped: bool,
+}
+
+impl TransferWaiter {
+    /// How often the semaphores are polled while tickets are awaited
+    const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+    pub(crate) fn new(device: ash::Device) -> Self {
+        let shared = Arc::new(WaiterShared::default());
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || Self::run(&device, &thread_shared));
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(device: &ash::Device, shared: &WaiterShared) {
+        let mut state = shared.state.lock();
+        while !state.stopped {
+            if state.pending.is_empty() {
+                shared.changed.wait(&mut state);
+                continue;
+            }
+
+            // Polled with the lock held, a ticket can't destroy its semaphore meanwhile. A failed
+            // query is reported by the next poll of the ticket
+            let mut done = Vec::new();
+            state.pending.retain(|(semaphore, waker)| {
+                let value = unsafe { device.get_semaphore_counter_value(*semaphore) };
+                if matches!(value, Ok(value) if value < TransferTicket::SIGNAL_VALUE) {
+                    return true;
+                }
+                done.push(waker.clone());
+                false
+            });
+
+            if !done.is_empty() {
+                drop(state);
+                done.into_iter().for_each(Waker::wake);
+                state = shared.state.lock();
+                continue;
+            }
+            shared.changed.wait_for(&mut state, Self::POLL_INTERVAL);
+        }
+    }
+
+    /// Wake `waker` once `semaphore` reaches [TransferTicket::SIGNAL_VALUE], in place of the
+    /// waker of a previous poll.
+    fn register(&self, semaphore: vk::Semaphore, waker: &Waker) {
+        let mut state = self.shared.state.lock();
+        match state
+            .pending
+            .iter_mut()
+            .find(|(pending, _)| *pending == semaphore)
+        {
+            Some((_, registered)) => registered.clone_from(waker),
+            None => {
+                state.pending.push((semaphore, waker.clone()));
+                self.shared.changed.notify_one();
+            }
+        }
+    }
+
+    /// Stop polling `semaphore`, which can be destroyed once this returns.
+    fn unregister(&self, semaphore: vk::Semaphore) {
+        self.shared
+            .state
+            .lock()
+            .pending
+            .retain(|(pending, _)| *pending != semaphore);
+    }
+
+    /// Stop the thread, before the device is destroyed.
+    pub(crate) fn stop(&mut self) {
+        self.shared.state.lock().stopped = true;
+        self.shared.changed.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
 
-            // Drop queue
-            drop(queue);
+impl TransferTicket<'_> {
+    /// The value the [semaphore](Self::semaphore) reaches when the transfers are done.
+    pub const SIGNAL_VALUE: u64 = 1;
 
-            // Wait
-            device.wait_for_fences(from_ref(&fence), true, u64::MAX)?;
-            device.destroy_fence(fence, None);
+    /// The timeline semaphore signaled by the transfers, for GPU work to wait on. It is
+    /// destroyed along with the ticket.
+    pub fn semaphore(&self) -> vk::Semaphore {
+        self.semaphore
+    }
 
-            // Destroy staging
-            for buff in self.to_destroy {
-                self.vma.destroy_buffer(buff.handle, buff.allocation);
+    /// Whether the transfers are done, without blocking.
+    pub fn is_complete(&mut self) -> Result<bool> {
+        if !self.released {
+            let value = unsafe {
+                self.renderer
+                    .device
+                    .get_semaphore_counter_value(self.semaphore)?
+            };
+            if value < Self::SIGNAL_VALUE {
+                return Ok(false);
             }
+            self.release();
+        }
+        Ok(true)
+    }
+
+    /// Block until the transfers are done.
+    pub fn wait(mut self) -> Result<()> {
+        self.wait_and_release()
+    }
+
+    /// The data copied back by the transfers, blocking until they are done. Fails if
+    /// `readback` was made by another context.
+    pub fn read<T: Copy>(&mut self, readback: Readback<T>) -> Result<Vec<T>> {
+        if readback.ticket != self.id {
+            return Err(PompeiiError::ForeignReadback);
         }
+        self.wait_and_release()?;
 
+        // Readbacks can't be copied, each one is only redeemed once
+        let buffer = self.readbacks[readback.index].take().unwrap();
+        let data = unsafe { self.renderer.load_from_buffer(&buffer, readback.len) };
+        unsafe { self.renderer.free_buffer(buffer) };
+        data
+    }
+
+    fn wait_and_release(&mut self) -> Result<()> {
+        if !self.released {
+            unsafe {
+                self.renderer.device.wait_semaphores(
+                    &vk::SemaphoreWaitInfo::builder()
+                        .semaphores(from_ref(&self.semaphore))
+                        .values(&[Self::SIGNAL_VALUE]),
+                    u64::MAX,
+                )?;
+            }
+            self.release();
+        }
         Ok(())
     }
+
+    fn release(&mut self) {
+        self.renderer.transfer_waiter.unregister(self.semaphore);
+        unsafe {
+            release_transfers(
+                self.renderer,
+                self.queue_index,
+                self.cmd,
+                self.semaphore,
+                self.to_destroy.drain(..),
+            );
+        }
+        self.released = true;
+    }
+}
+
+unsafe fn release_transfers(
+    renderer: &PompeiiRenderer,
+    queue_index: usize,
+    cmd: vk::CommandBuffer,
+    semaphore: vk::Semaphore,
+    buffers: impl IntoIterator<Item = VkBufferHandle>,
+) {
+    let queue = renderer.queues.by_index(queue_index);
+    renderer
+        .device
+        .free_command_buffers(queue.pool, from_ref(&cmd));
+    drop(queue);
+
+    for buff in buffers {
+        renderer.free_buffer(buff);
+    }
+    renderer.device.destroy_semaphore(semaphore, None);
+}
+
+/// The renderer polls the semaphore from another thread while the ticket is awaited, and wakes
+/// the task once the transfers are done.
+impl Future for TransferTicket<'_> {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Registered before checking, so transfers completing in between wake the right task
+        if !self.released {
+            self.renderer
+                .transfer_waiter
+                .register(self.semaphore, cx.waker());
+        }
+
+        match self.is_complete() {
+            Ok(false) => Poll::Pending,
+            Ok(true) => Poll::Ready(Ok(())),
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+impl Drop for TransferTicket<'_> {
+    fn drop(&mut self) {
+        let readbacks = self.readbacks.drain(..).flatten();
+        if self.released {
+            for buffer in readbacks {
+                unsafe { self.renderer.free_buffer(buffer) };
+            }
+            return;
+        }
+
+        // Release once the transfers are done, without blocking
+        let (queue_index, cmd, semaphore) = (self.queue_index, self.cmd, self.semaphore);
+        self.renderer.transfer_waiter.unregister(semaphore);
+        let buffers = self
+            .to_destroy
+            .drain(..)
+            .chain(readbacks)
+            .collect::<Vec<_>>();
+        self.renderer
+            .defer_deletion_until(semaphore, Self::SIGNAL_VALUE, move |renderer| {
+                unsafe { release_transfers(renderer, queue_index, cmd, semaphore, buffers) };
+                Ok(())
+            });
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -188,26 +930,107 @@ pub struct VkBufferHandle {
     pub(crate) handle: vk::Buffer,
     pub(crate) allocation: vk_mem::Allocation,
     pub(crate) info: vk_mem::AllocationInfo,
+    /// Size of the buffer, the allocation can be larger
+    pub(crate) size: vk::DeviceSize,
+    /// Counted in the [memory report](PompeiiRenderer::memory_report) until freed
+    pub(crate) category: Option<MemoryCategory>,
 }
 
 unsafe impl Send for VkBufferHandle {}
 unsafe impl Sync for VkBufferHandle {}
 
-impl From<(vk::Buffer, vk_mem::Allocation, vk_mem::AllocationInfo)> for VkBufferHandle {
-    fn from(
-        (handle, allocation, info): (vk::Buffer, vk_mem::Allocation, vk_mem::AllocationInfo),
-    ) -> Self {
+impl VkBufferHandle {
+    pub(crate) unsafe fn destroy(&self, vma: &vk_mem::Allocator) {
+        vma.destroy_buffer(self.handle, self.allocation);
+    }
+}
+
+/// A buffer owning its memory, destroyed once the GPU is done with the frames that may use it.
+#[derive(Debug)]
+pub struct Buffer {
+    raw: VkBufferHandle,
+    deletion: Arc<DeletionQueue>,
+}
+
+impl Buffer {
+    pub(crate) fn new(renderer: &PompeiiRenderer, raw: VkBufferHandle) -> Self {
         Self {
-            handle,
-            allocation,
-            info,
+            raw,
+            deletion: Arc::clone(&renderer.deletion_queue),
         }
     }
+
+    pub fn handle(&self) -> vk::Buffer {
+        self.raw.handle
+    }
 }
 
-impl VkBufferHandle {
-    pub unsafe fn destroy(&self, vma: &vk_mem::Allocator) {
-        vma.destroy_buffer(self.handle, self.allocation);
+impl Deref for Buffer {
+    type Target = VkBufferHandle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.raw
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.deletion.push(Deletion::Buffer(self.raw.clone()));
+    }
+}
+
+/// An image owning its memory and a view of all of it, destroyed once the GPU is done with the
+/// frames that may use it.
+#[derive(Debug)]
+pub struct Image {
+    pub(crate) handle: vk::Image,
+    pub(crate) allocation: vk_mem::Allocation,
+    pub(crate) view: vk::ImageView,
+    pub(crate) format: vk::Format,
+    pub(crate) extent: vk::Extent3D,
+    pub(crate) mip_levels: u32,
+    deletion: Arc<DeletionQueue>,
+}
+
+unsafe impl Send for Image {}
+unsafe impl Sync for Image {}
+
+impl Image {
+    pub fn image(&self) -> vk::Image {
+        self.handle
+    }
+
+    pub fn view(&self) -> vk::ImageView {
+        self.view
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn extent(&self) -> vk::Extent3D {
+        self.extent
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub(crate) fn full_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: format_aspect(self.format),
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        self.deletion
+            .push(Deletion::Image(self.handle, self.view, self.allocation));
     }
 }
 
@@ -215,11 +1038,14 @@ impl VkBufferHandle {
 impl PompeiiRenderer {
     pub(crate) fn alloc_staging_buffer(&self, size: vk::DeviceSize) -> Result<VkBufferHandle> {
         unsafe {
-            let handle = self.create_buffer(
-                size,
-                vk::BufferUsageFlags::TRANSFER_SRC,
-                vk_mem::MemoryUsage::CpuOnly,
-            )?;
+            let handle = self.track_buffer(
+                self.create_buffer(
+                    size,
+                    vk::BufferUsageFlags::TRANSFER_SRC,
+                    vk_mem::MemoryUsage::CpuOnly,
+                )?,
+                MemoryCategory::Staging,
+            );
 
             self.debug_utils.name_buffer(
                 &self.device,
@@ -231,80 +1057,147 @@ impl PompeiiRenderer {
         }
     }
 
-    pub(crate) fn alloc_vertex_buffer(&self, size: vk::DeviceSize) -> Result<VkBufferHandle> {
-        unsafe {
+    pub(crate) fn alloc_readback_buffer(&self, size: vk::DeviceSize) -> Result<VkBufferHandle> {
+        let buffer = unsafe {
             self.create_buffer(
                 size,
-                vk::BufferUsageFlags::TRANSFER_DST
-                    | vk::BufferUsageFlags::VERTEX_BUFFER
-                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
-                vk_mem::MemoryUsage::GpuOnly,
-            )
-        }
+                vk::BufferUsageFlags::TRANSFER_DST,
+                vk_mem::MemoryUsage::GpuToCpu,
+            )?
+        };
+        let buffer = self.track_buffer(buffer, MemoryCategory::Readback);
+
+        self.debug_utils.name_buffer(
+            &self.device,
+            buffer.handle,
+            &CString::new(format!("Readback Buffer (size: {})", size)).unwrap(),
+        )?;
+
+        Ok(buffer)
     }
 
-    pub(crate) fn alloc_index_buffer(&self, size: vk::DeviceSize) -> Result<VkBufferHandle> {
+    pub(crate) fn alloc_texture_image(
+        &self,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        mip_levels: u32,
+        usage: vk::ImageUsageFlags,
+    ) -> Result<Image> {
+        let extent = vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        };
+
+        // Uploaded on the transfer queue and sampled on the others, without ownership transfers
+        let indices = &self.queues.indices;
+        let mut families = vec![indices.graphics, indices.compute, indices.transfer];
+        families.sort_unstable();
+        families.dedup();
+        let sharing_mode = if families.len() > 1 {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        };
+
         unsafe {
-            self.create_buffer(
-                size,
-                vk::BufferUsageFlags::TRANSFER_DST
-                    | vk::BufferUsageFlags::INDEX_BUFFER
-                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
-                vk_mem::MemoryUsage::GpuOnly,
-            )
+            let (handle, allocation, _) = self.vma.create_image(
+                &vk::ImageCreateInfo::builder()
+                    .image_type(vk::ImageType::TYPE_2D)
+                    .format(format)
+                    .extent(extent)
+                    .mip_levels(mip_levels)
+                    .array_layers(1)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .tiling(vk::ImageTiling::OPTIMAL)
+                    .usage(usage)
+                    .sharing_mode(sharing_mode)
+                    .queue_family_indices(&families)
+                    .initial_layout(vk::ImageLayout::UNDEFINED),
+                &vk_mem::AllocationCreateInfo::new().usage(vk_mem::MemoryUsage::GpuOnly),
+            )?;
+
+            let mut image = Image {
+                handle,
+                allocation,
+                view: vk::ImageView::null(),
+                format,
+                extent,
+                mip_levels,
+                deletion: Arc::clone(&self.deletion_queue),
+            };
+            self.memory_counters.track(MemoryCategory::Texture);
+            image.view = create_image_view(
+                &self.device,
+                handle,
+                vk::ImageViewType::TYPE_2D,
+                format,
+                image.full_range(),
+            )?;
+
+            Ok(image)
         }
     }
 
+    /// Whether optimal images of the format can be the source and destination of linear blits.
+    pub(crate) fn supports_linear_blit(&self, format: vk::Format) -> bool {
+        let properties = unsafe {
+            self.instance
+                .get_physical_device_format_properties(self.physical_device, format)
+        };
+        properties.optimal_tiling_features.contains(
+            vk::FormatFeatureFlags::BLIT_SRC
+                | vk::FormatFeatureFlags::BLIT_DST
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+        )
+    }
+
+    /// `vkCmdBlitImage` needs a queue supporting graphics operations.
+    pub(crate) fn transfer_queue_can_blit(&self) -> bool {
+        let families = unsafe {
+            self.instance
+                .get_physical_device_queue_family_properties(self.physical_device)
+        };
+        families[self.queues.indices.transfer as usize]
+            .queue_flags
+            .contains(vk::QueueFlags::GRAPHICS)
+    }
+
     pub(crate) fn alloc_acceleration_structure_scratch_buffer(
         &self,
         size: vk::DeviceSize,
     ) -> Result<VkBufferHandle> {
-        unsafe {
+        let buffer = unsafe {
             self.create_buffer_from_pool(
                 size,
                 vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
                 vk_mem::MemoryUsage::GpuOnly,
                 self.vma_pools.acceleration_structures,
-            )
-        }
+            )?
+        };
+        Ok(self.track_buffer(buffer, MemoryCategory::Scratch))
     }
 
     pub(crate) fn alloc_acceleration_structure_buffer(
         &self,
         size: vk::DeviceSize,
     ) -> Result<VkBufferHandle> {
-        unsafe {
+        let buffer = unsafe {
             self.create_buffer(
                 size,
                 vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
                     | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
                 vk_mem::MemoryUsage::GpuOnly,
-            )
-        }
-    }
-
-    pub(crate) fn alloc_acceleration_structure_instance_buffer(
-        &self,
-        size: vk::DeviceSize,
-    ) -> Result<VkBufferHandle> {
-        unsafe {
-            self.create_buffer(
-                size,
-                vk::BufferUsageFlags::TRANSFER_DST
-                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
-                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
-                vk_mem::MemoryUsage::GpuOnly,
-            )
-        }
+            )?
+        };
+        Ok(self.track_buffer(buffer, MemoryCategory::AccelerationStructure))
     }
 }
 
 // Low level methods
 impl PompeiiRenderer {
     #[inline]
-    unsafe fn create_buffer(
+    pub(crate) unsafe fn create_buffer(
         &self,
         size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
@@ -329,25 +1222,36 @@ impl PompeiiRenderer {
             trace!("- Pool: {:?}", pool);
         }
 
-        Ok(self
-            .vma
-            .create_buffer(
-                &vk::BufferCreateInfo::builder()
-                    .size(size)
-                    .usage(usage)
-                    .sharing_mode(vk::SharingMode::EXCLUSIVE),
-                &vk_mem::AllocationCreateInfo::new()
-                    .usage(location)
-                    .pool(pool),
-            )?
-            .into())
+        let (handle, allocation, info) = self.vma.create_buffer(
+            &vk::BufferCreateInfo::builder()
+                .size(size)
+                .usage(usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE),
+            &vk_mem::AllocationCreateInfo::new()
+                .usage(location)
+                .pool(pool),
+        )?;
+        Ok(VkBufferHandle {
+            handle,
+            allocation,
+            info,
+            size,
+            category: None,
+        })
+    }
+
+    /// Whether the memory type of the buffer can be mapped.
+    unsafe fn is_host_visible(&self, buffer: &VkBufferHandle) -> bool {
+        let properties = self
+            .instance
+            .get_physical_device_memory_properties(self.physical_device);
+        properties.memory_types[buffer.info.get_memory_type() as usize]
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
     }
 
     unsafe fn store_to_buffer<D: Copy>(&self, buffer: &VkBufferHandle, data: &[D]) -> Result<()> {
-        debug_assert_ne!(
-            (buffer.info.get_memory_type() & vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw()),
-            0
-        );
+        debug_assert!(self.is_host_visible(buffer));
 
         let (need_unmap, mapped_ptr) = if buffer.info.get_mapped_data().is_null() {
             (true, self.vma.map_memory(buffer.allocation)?)
@@ -369,4 +1273,32 @@ impl PompeiiRenderer {
 
         Ok(())
     }
+
+    unsafe fn load_from_buffer<D: Copy>(
+        &self,
+        buffer: &VkBufferHandle,
+        len: usize,
+    ) -> Result<Vec<D>> {
+        debug_assert!(self.is_host_visible(buffer));
+
+        let (need_unmap, mapped_ptr) = if buffer.info.get_mapped_data().is_null() {
+            (true, self.vma.map_memory(buffer.allocation)?)
+        } else {
+            (false, buffer.info.get_mapped_data())
+        };
+
+        let size = std::mem::size_of::<D>() * len;
+        self.vma.invalidate_allocation(buffer.allocation, 0, size)?;
+
+        let mut data = Vec::with_capacity(len);
+        // The mapping isn't necessarily aligned for D
+        ptr::copy_nonoverlapping(mapped_ptr as *const u8, data.as_mut_ptr() as *mut u8, size);
+        data.set_len(len);
+
+        if need_unmap {
+            self.vma.unmap_memory(buffer.allocation);
+        }
+
+        Ok(data)
+    }
 }
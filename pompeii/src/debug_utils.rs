@@ -52,6 +52,24 @@ impl DebugUtils {
         }
         Ok(())
     }
+
+    pub(crate) fn name_image(
+        &self,
+        device: &ash::Device,
+        image: vk::Image,
+        name: &CStr,
+    ) -> Result<()> {
+        unsafe {
+            self.loader.debug_utils_set_object_name(
+                device.handle(),
+                &vk::DebugUtilsObjectNameInfoEXT::builder()
+                    .object_type(vk::ObjectType::IMAGE)
+                    .object_handle(image.as_raw())
+                    .object_name(name),
+            )?;
+        }
+        Ok(())
+    }
 }
 
 impl DebugUtils {
@@ -0,0 +1,165 @@
+//! GPU objects freed once the work that may use them is done
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use ash::vk;
+use log::{error, trace};
+use parking_lot::Mutex;
+
+use crate::{alloc::VkBufferHandle, errors::Result, memory::MemoryCategory, PompeiiRenderer};
+
+/// Destroys whatever it captured.
+pub(crate) type DeferredDeletion = Box<dyn FnOnce(&PompeiiRenderer) -> Result<()> + Send + Sync>;
+
+/// An object to destroy, along with its memory.
+pub(crate) enum Deletion {
+    Buffer(VkBufferHandle),
+    Image(vk::Image, vk::ImageView, vk_mem::Allocation),
+    AccelerationStructure(vk::AccelerationStructureKHR, VkBufferHandle),
+    Deferred(DeferredDeletion),
+}
+
+unsafe impl Send for Deletion {}
+unsafe impl Sync for Deletion {}
+
+impl fmt::Debug for Deletion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Deletion::Buffer(buffer) => f.debug_tuple("Buffer").field(&buffer.handle).finish(),
+            Deletion::Image(image, view, _) => {
+                f.debug_tuple("Image").field(image).field(view).finish()
+            }
+            Deletion::AccelerationStructure(handle, buffer) => f
+                .debug_tuple("AccelerationStructure")
+                .field(handle)
+                .field(&buffer.handle)
+                .finish(),
+            Deletion::Deferred(_) => f.write_str("Deferred"),
+        }
+    }
+}
+
+/// When the GPU is done with what a deletion is about.
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum DeletionPoint {
+    /// Once that many frames are done
+    Frame(u64),
+    /// Once the timeline semaphore reaches the value
+    Timeline(vk::Semaphore, u64),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct DeletionQueue {
+    submitted: AtomicU64,
+    pending: Mutex<Vec<(DeletionPoint, Deletion)>>,
+}
+
+impl DeletionQueue {
+    /// Delete once the frames submitted so far are done.
+    pub(crate) fn push(&self, deletion: Deletion) {
+        let frame = self.submitted.load(Ordering::Acquire);
+        self.push_at(DeletionPoint::Frame(frame), deletion);
+    }
+
+    pub(crate) fn push_at(&self, point: DeletionPoint, deletion: Deletion) {
+        self.pending.lock().push((point, deletion));
+    }
+
+    /// A frame was submitted, what gets queued from now on may be used by it.
+    pub(crate) fn end_frame(&self) {
+        self.submitted.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Take the deletions whose point was reached, in the order they were queued.
+    fn take_reached(&self, reached: impl Fn(DeletionPoint) -> bool) -> Vec<Deletion> {
+        let mut pending = self.pending.lock();
+        let (done, left) = pending.drain(..).partition(|(point, _)| reached(*point));
+        *pending = left;
+        done.into_iter().map(|(_, deletion)| deletion).collect()
+    }
+
+    fn take_all(&self) -> Vec<Deletion> {
+        self.pending
+            .lock()
+            .drain(..)
+            .map(|(_, deletion)| deletion)
+            .collect()
+    }
+}
+
+impl PompeiiRenderer {
+    /// Run `destroy` once the GPU is done with the frames submitted so far, for objects they may
+    /// still use.
+    pub fn defer_deletion(
+        &self,
+        destroy: impl FnOnce(&PompeiiRenderer) -> Result<()> + Send + Sync + 'static,
+    ) {
+        self.deletion_queue
+            .push(Deletion::Deferred(Box::new(destroy)));
+    }
+
+    /// Run `destroy` once `semaphore`, a timeline semaphore, reaches `value`. The semaphore must
+    /// outlive the call, `destroy` can be what destroys it.
+    pub fn defer_deletion_until(
+        &self,
+        semaphore: vk::Semaphore,
+        value: u64,
+        destroy: impl FnOnce(&PompeiiRenderer) -> Result<()> + Send + Sync + 'static,
+    ) {
+        self.deletion_queue.push_at(
+            DeletionPoint::Timeline(semaphore, value),
+            Deletion::Deferred(Box::new(destroy)),
+        );
+    }
+
+    /// Destroy what was dropped up to the last submitted frame, along with what waits on
+    /// timeline values already reached.
+    ///
+    /// Every submitted frame must be done, and the next one not recorded yet.
+    pub(crate) unsafe fn destroy_completed(&self) {
+        let completed = self.deletion_queue.submitted.load(Ordering::Acquire);
+        let reached = self.deletion_queue.take_reached(|point| match point {
+            DeletionPoint::Frame(frame) => frame <= completed,
+            DeletionPoint::Timeline(semaphore, value) => self
+                .device
+                .get_semaphore_counter_value(semaphore)
+                .map(|current| current >= value)
+                .unwrap_or(false),
+        });
+
+        for deletion in reached {
+            self.destroy_now(deletion);
+        }
+    }
+
+    /// Destroy everything left, once the device is idle.
+    pub(crate) unsafe fn destroy_pending(&self) {
+        for deletion in self.deletion_queue.take_all() {
+            self.destroy_now(deletion);
+        }
+    }
+
+    unsafe fn destroy_now(&self, deletion: Deletion) {
+        trace!("Destroy {:?}", deletion);
+        match deletion {
+            Deletion::Buffer(buffer) => self.free_buffer(buffer),
+            Deletion::Image(image, view, allocation) => {
+                self.device.destroy_image_view(view, None);
+                self.vma.destroy_image(image, allocation);
+                self.memory_counters.untrack(MemoryCategory::Texture);
+            }
+            Deletion::AccelerationStructure(handle, buffer) => {
+                self.ext_acceleration_structure
+                    .destroy_acceleration_structure(handle, None);
+                self.free_buffer(buffer);
+            }
+            Deletion::Deferred(destroy) => {
+                if let Err(err) = destroy(self) {
+                    error!("Deferred deletion failed: {}", err);
+                }
+            }
+        }
+    }
+}
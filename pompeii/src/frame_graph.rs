@@ -0,0 +1,1105 @@
+use std::{
+    array::from_ref,
+    collections::{hash_map::Entry, HashMap},
+    ffi::CString,
+};
+
+use ash::vk;
+use log::{debug, warn};
+use parking_lot::ReentrantMutexGuard;
+use pompeii_task::{
+    plan_aliasing, AliasingBarrier, Barrier, CacheStats, CompiledGraph, CompiledGraphCache,
+    ExternalState, History, ImageDesc, MemoryRequirements, PassContext, QueueType, RenderScope,
+    Res, ResolvedImage, ResolvedResources, SubresourceRange, TaskGraph,
+};
+use vk_sync_fork::{AccessType, BufferBarrier, GlobalBarrier, ImageBarrier, ImageLayout};
+
+use crate::{
+    alloc::VkBufferHandle,
+    errors::{PompeiiError, Result},
+    images::{create_image_view, format_aspect},
+    setup::QueueWithPool,
+    PompeiiRenderer,
+};
+
+/// The task graph rendering a frame, tagged with debug names.
+pub type FrameTaskGraph = TaskGraph<&'static str, &'static str>;
+
+/// Adds the passes of a frame to a graph, given the swapchain image to draw to.
+pub type FrameGraphBuilder = Box<dyn Fn(&mut FrameTaskGraph, Res) + Send + Sync>;
+
+/// Which half of a queue family ownership transfer a barrier is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BarrierHalf {
+    /// Not a transfer, or one between queues of the same family
+    Full,
+    Release,
+    Acquire,
+}
+
+/// A frame graph along with everything it needs to run.
+pub(crate) struct FrameGraph {
+    graph: FrameTaskGraph,
+    backbuffer: Res,
+    /// Compiled and allocated again only when a rebuilt graph has another structure
+    cache: CompiledGraphCache<FrameAllocations>,
+    /// Number of frames rendered, to know which allocation of the histories is current
+    frame_index: usize,
+    /// Command buffers of the frame being rendered
+    in_flight: Vec<(QueueType, vk::CommandBuffer)>,
+}
+
+/// A command buffer of the frame, ready to be submitted.
+pub(crate) struct RecordedSubmission {
+    queue: QueueType,
+    cmd: vk::CommandBuffer,
+    waits: Vec<vk::Semaphore>,
+    signals: Vec<vk::Semaphore>,
+}
+
+/// The Vulkan objects a compiled frame graph runs on.
+#[derive(Default)]
+struct FrameAllocations {
+    resources: ResolvedResources,
+    images: Vec<(vk::Image, vk_mem::Allocation)>,
+    buffers: Vec<VkBufferHandle>,
+    /// Transient resources, bound to `aliased_memory`
+    aliased_images: Vec<vk::Image>,
+    aliased_buffers: Vec<vk::Buffer>,
+    /// The block transient resources share, and the memory of the ones that couldn't
+    aliased_memory: Vec<vk_mem::Allocation>,
+    /// Views of the whole images and of some of their mips or layers
+    views: Vec<vk::ImageView>,
+    /// Release halves of the ownership transfers, to record after the pass at the same index
+    releases: Vec<Vec<Barrier>>,
+    /// Memory handed over between transient resources, to record before the pass at the same
+    /// index
+    aliasing: Vec<Vec<AliasingBarrier>>,
+    /// Semaphores between submissions as `(signaled by, waited by, semaphore)`, the number of
+    /// submissions standing for the final one
+    links: Vec<(usize, usize, vk::Semaphore)>,
+    /// Both allocations of every history, the one written during a frame alternating
+    histories: Vec<(History, [Resolved; 2])>,
+}
+
+/// The Vulkan objects behind a resource of the graph.
+#[derive(Debug, Clone)]
+enum Resolved {
+    Image(ResolvedImage, Vec<(SubresourceRange, vk::ImageView)>),
+    Buffer(vk::Buffer),
+}
+
+impl Resolved {
+    fn bind(&self, resources: &mut ResolvedResources, res: Res) {
+        match self {
+            Resolved::Image(image, range_views) => {
+                resources.bind_image(res, *image);
+                for &(range, view) in range_views {
+                    resources.bind_image_range_view(res, range, view);
+                }
+            }
+            Resolved::Buffer(buffer) => resources.bind_buffer(res, *buffer),
+        }
+    }
+}
+
+// Allocations are only touched with the frame graph lock held
+unsafe impl Send for FrameGraph {}
+
+impl FrameGraph {
+    pub(crate) unsafe fn destroy(mut self, renderer: &PompeiiRenderer) {
+        self.free_command_buffers(renderer);
+
+        if let Some(allocations) = self.cache.take() {
+            allocations.destroy(renderer);
+        }
+    }
+
+    unsafe fn free_command_buffers(&mut self, renderer: &PompeiiRenderer) {
+        for (queue, cmd) in self.in_flight.drain(..) {
+            let queue = renderer.lock_queue(queue);
+            renderer.device.free_command_buffers(queue.pool, &[cmd]);
+        }
+    }
+}
+
+impl FrameAllocations {
+    unsafe fn destroy(self, renderer: &PompeiiRenderer) {
+        for view in self.views {
+            renderer.device.destroy_image_view(view, None);
+        }
+        for (image, allocation) in self.images {
+            renderer.vma.destroy_image(image, allocation);
+        }
+        for buffer in self.buffers {
+            buffer.destroy(&renderer.vma);
+        }
+        for image in self.aliased_images {
+            renderer.device.destroy_image(image, None);
+        }
+        for buffer in self.aliased_buffers {
+            renderer.device.destroy_buffer(buffer, None);
+        }
+        for allocation in self.aliased_memory {
+            renderer.vma.free_memory(allocation);
+        }
+        for (_, _, semaphore) in self.links {
+            renderer.device.destroy_semaphore(semaphore, None);
+        }
+    }
+}
+
+impl PompeiiRenderer {
+    /// Replace what gets rendered every frame.
+    ///
+    /// `builder` adds its passes to a graph in which the swapchain image is already imported,
+    /// to be presented after the last pass. It is called again every time the swapchain is recreated.
+    pub fn set_frame_graph(
+        &self,
+        builder: impl Fn(&mut FrameTaskGraph, Res) + Send + Sync + 'static,
+    ) -> Result<()> {
+        *self.frame_graph_builder.lock() = Box::new(builder);
+        self.rebuild_frame_graph()
+    }
+
+    /// Statistics of the compiled frame graph cache, a hit meaning the rebuilt graph had the
+    /// same structure and the previous allocations were kept.
+    pub fn frame_graph_cache_stats(&self) -> CacheStats {
+        self.frame_graph
+            .lock()
+            .as_ref()
+            .map(|frame| frame.cache.stats())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn rebuild_frame_graph(&self) -> Result<()> {
+        let mut frame_graph = self.frame_graph.lock();
+
+        let (format, extent) = {
+            let swapchain = self.swapchain.read();
+            (swapchain.format, swapchain.extent)
+        };
+
+        // Whatever was presented before is overwritten
+        let mut graph = FrameTaskGraph::new();
+        let backbuffer = graph.import_image(
+            "Backbuffer",
+            ImageDesc::new_2d(extent.width, extent.height, format),
+            ExternalState::new(AccessType::Nothing),
+            ExternalState::new(AccessType::Present),
+        );
+        (self.frame_graph_builder.lock())(&mut graph, backbuffer);
+        // ash has no VK_KHR_dynamic_rendering_local_read, every pass renders in its own scope
+        // and samples its input attachments
+        graph.set_local_read(false);
+
+        for diagnostic in graph.validate() {
+            warn!("[Frame graph] {}", diagnostic);
+        }
+
+        // The frame in flight keeps using the allocations until the graph gets recompiled, only
+        // the record callbacks are replaced
+        let frame = frame_graph.get_or_insert_with(|| FrameGraph {
+            graph: FrameTaskGraph::new(),
+            backbuffer,
+            cache: CompiledGraphCache::new(),
+            frame_index: 0,
+            in_flight: Vec::new(),
+        });
+        frame.graph = graph;
+        frame.backbuffer = backbuffer;
+
+        self.compile_frame_graph(frame)
+    }
+
+    /// Compile the graph and allocate its resources, unless its structure didn't change since
+    /// the last time. Called every frame, as toggling a pass changes the structure.
+    fn compile_frame_graph(&self, frame: &mut FrameGraph) -> Result<()> {
+        let misses = frame.cache.stats().misses;
+        frame
+            .cache
+            .get_or_compile(&frame.graph, |compiled, previous| unsafe {
+                // The previous graph must be done with its allocations
+                if let Some(previous) = previous.take() {
+                    let _graphics = self.queues.graphics();
+                    let _present = self.queues.present();
+                    let _compute = self.queues.compute();
+                    let _transfer = self.queues.transfer();
+
+                    self.device.device_wait_idle()?;
+                    previous.destroy(self);
+                }
+
+                self.allocate_frame_graph(&frame.graph, compiled)
+            })?;
+
+        if frame.cache.stats().misses > misses {
+            if let Some((compiled, _)) = frame.cache.get_mut() {
+                debug!(
+                    "[Frame graph] {} passes in {} submissions, {} culled",
+                    compiled.passes().len(),
+                    compiled.submissions().len(),
+                    compiled.culled_passes().len()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    unsafe fn allocate_frame_graph(
+        &self,
+        graph: &FrameTaskGraph,
+        compiled: &CompiledGraph,
+    ) -> Result<FrameAllocations> {
+        let mut allocations = FrameAllocations {
+            releases: self.ownership_releases(compiled),
+            aliasing: vec![Vec::new(); compiled.passes().len()],
+            ..Default::default()
+        };
+
+        // Everything is added to the allocations as soon as it is created, so what was created
+        // before a failure can be destroyed with them
+        let result = self
+            .allocate_frame_graph_resources(graph, compiled, &mut allocations)
+            .and_then(|()| self.initialize_frame_graph_histories(graph, compiled, &allocations))
+            .and_then(|()| self.create_frame_graph_semaphores(compiled, &mut allocations));
+        if let Err(err) = result {
+            allocations.destroy(self);
+            return Err(err);
+        }
+
+        Ok(allocations)
+    }
+
+    /// Transient resources share memory according to the aliasing plan of the graph, the other
+    /// resources used by the passes get their own except for the imported ones. History
+    /// resources get two, swapped every frame.
+    // TODO: only the backbuffer gets bound, builders can't provide other imports yet
+    unsafe fn allocate_frame_graph_resources(
+        &self,
+        graph: &FrameTaskGraph,
+        compiled: &CompiledGraph,
+        allocations: &mut FrameAllocations,
+    ) -> Result<()> {
+        let mut used = compiled
+            .passes()
+            .iter()
+            .flat_map(|pass| {
+                pass.reads
+                    .iter()
+                    .map(|read| read.resource)
+                    .chain(pass.writes.iter().map(|write| write.resource))
+            })
+            .filter(|&res| !graph.is_imported(res) && !graph.is_history(res))
+            .collect::<Vec<_>>();
+        used.sort_unstable();
+        used.dedup();
+
+        // Also used in place of the resources they are forwarded from
+        let usage_of = |res| {
+            std::iter::once(res)
+                .chain(
+                    compiled
+                        .forwarded_resources()
+                        .iter()
+                        .filter(|&&(_, to)| to == res)
+                        .map(|&(from, _)| from),
+                )
+                .collect::<Vec<_>>()
+        };
+
+        let transient = compiled
+            .transient_lifetimes()
+            .iter()
+            .map(|lifetime| lifetime.resource)
+            .collect::<Vec<_>>();
+        for &res in used.iter().filter(|res| !transient.contains(res)) {
+            let resolved =
+                self.create_frame_graph_resource(graph, allocations, res, &usage_of(res))?;
+            resolved.bind(&mut allocations.resources, res);
+        }
+        self.allocate_aliased_frame_graph_resources(graph, compiled, allocations, usage_of)?;
+
+        for &history in graph.histories() {
+            let usage_of = [history.current, history.previous];
+            let slots = [
+                self.create_frame_graph_resource(graph, allocations, history.current, &usage_of)?,
+                self.create_frame_graph_resource(graph, allocations, history.current, &usage_of)?,
+            ];
+            allocations.histories.push((history, slots));
+        }
+
+        Ok(())
+    }
+
+    /// Allocate the memory of `res`, usable the way all of `usage_of` are used.
+    unsafe fn create_frame_graph_resource(
+        &self,
+        graph: &FrameTaskGraph,
+        allocations: &mut FrameAllocations,
+        res: Res,
+        usage_of: &[Res],
+    ) -> Result<Resolved> {
+        if let Some(desc) = graph.image_desc(res) {
+            let (image, allocation, _) = self.vma.create_image(
+                &frame_graph_image_info(graph, desc, usage_of),
+                &vk_mem::AllocationCreateInfo::new().usage(vk_mem::MemoryUsage::GpuOnly),
+            )?;
+            allocations.images.push((image, allocation));
+
+            self.create_frame_graph_views(graph, allocations, res, usage_of, image)
+        } else {
+            let buffer = self.create_buffer(
+                graph.buffer_size(res).unwrap(),
+                frame_graph_buffer_usage(graph, usage_of),
+                vk_mem::MemoryUsage::GpuOnly,
+            )?;
+            let handle = buffer.handle;
+            allocations.buffers.push(buffer);
+
+            self.name_frame_graph_buffer(graph, res, handle)
+        }
+    }
+
+    /// Place the transient resources in a single block of memory, the ones that are never in
+    /// use at the same time sharing the same range.
+    ///
+    /// The resources whose memory types are incompatible with the others get their own.
+    unsafe fn allocate_aliased_frame_graph_resources(
+        &self,
+        graph: &FrameTaskGraph,
+        compiled: &CompiledGraph,
+        allocations: &mut FrameAllocations,
+        usage_of: impl Fn(Res) -> Vec<Res>,
+    ) -> Result<()> {
+        // Linear buffers and optimal images next to each other must be that far apart
+        let granularity = self
+            .instance
+            .get_physical_device_properties(self.physical_device)
+            .limits
+            .buffer_image_granularity;
+
+        let mut requirements = HashMap::new();
+        let mut memory_type_bits = !0;
+        let mut shared = Vec::new();
+        for lifetime in compiled.transient_lifetimes() {
+            let res = lifetime.resource;
+            let usage_of = usage_of(res);
+
+            let req = if let Some(desc) = graph.image_desc(res) {
+                let image = self
+                    .device
+                    .create_image(&frame_graph_image_info(graph, desc, &usage_of), None)?;
+                allocations.aliased_images.push(image);
+                self.device.get_image_memory_requirements(image)
+            } else {
+                let buffer = self
+                    .device
+                    .create_buffer(&frame_graph_buffer_info(graph, res, &usage_of), None)?;
+                allocations.aliased_buffers.push(buffer);
+                self.device.get_buffer_memory_requirements(buffer)
+            };
+
+            if memory_type_bits & req.memory_type_bits != 0 {
+                memory_type_bits &= req.memory_type_bits;
+                shared.push(lifetime.clone());
+            }
+            requirements.insert(res, req);
+        }
+
+        let plan = plan_aliasing(&shared, |res| MemoryRequirements {
+            size: requirements[&res].size,
+            alignment: requirements[&res].alignment.max(granularity),
+        });
+        debug!(
+            "[Frame graph] {} transient resources in {} bytes",
+            plan.resources.len(),
+            plan.size
+        );
+
+        // Where every resource goes, as the memory and the offset in it
+        let mut placements = HashMap::new();
+        if plan.size > 0 {
+            let (memory, base) = self.allocate_frame_graph_memory(
+                allocations,
+                vk::MemoryRequirements {
+                    size: plan.size,
+                    alignment: shared
+                        .iter()
+                        .map(|lifetime| requirements[&lifetime.resource].alignment)
+                        .fold(granularity, vk::DeviceSize::max),
+                    memory_type_bits,
+                },
+            )?;
+            for placed in &plan.resources {
+                placements.insert(placed.resource, (memory, base + placed.offset));
+            }
+        }
+        for lifetime in compiled.transient_lifetimes() {
+            if let Entry::Vacant(entry) = placements.entry(lifetime.resource) {
+                entry.insert(
+                    self.allocate_frame_graph_memory(
+                        allocations,
+                        requirements[&lifetime.resource],
+                    )?,
+                );
+            }
+        }
+
+        let (mut images, mut buffers) = (
+            allocations.aliased_images.clone().into_iter(),
+            allocations.aliased_buffers.clone().into_iter(),
+        );
+        for lifetime in compiled.transient_lifetimes() {
+            let res = lifetime.resource;
+            let (memory, offset) = placements[&res];
+
+            let resolved = if graph.image_desc(res).is_some() {
+                let image = images.next().unwrap();
+                self.device.bind_image_memory(image, memory, offset)?;
+                self.create_frame_graph_views(graph, allocations, res, &usage_of(res), image)?
+            } else {
+                let buffer = buffers.next().unwrap();
+                self.device.bind_buffer_memory(buffer, memory, offset)?;
+                self.name_frame_graph_buffer(graph, res, buffer)?
+            };
+            resolved.bind(&mut allocations.resources, res);
+        }
+
+        for barrier in plan.barriers {
+            allocations.aliasing[barrier.pass].push(barrier);
+        }
+
+        Ok(())
+    }
+
+    /// The memory to bind the resources to, and where the allocation starts in it.
+    unsafe fn allocate_frame_graph_memory(
+        &self,
+        allocations: &mut FrameAllocations,
+        requirements: vk::MemoryRequirements,
+    ) -> Result<(vk::DeviceMemory, vk::DeviceSize)> {
+        let (allocation, info) = self.vma.allocate_memory(
+            &requirements,
+            &vk_mem::AllocationCreateInfo::new().usage(vk_mem::MemoryUsage::GpuOnly),
+        )?;
+        allocations.aliased_memory.push(allocation);
+        Ok((info.get_device_memory(), info.get_offset() as _))
+    }
+
+    /// Name the image and create its views, of the whole image and of every range of it
+    /// `usage_of` use.
+    unsafe fn create_frame_graph_views(
+        &self,
+        graph: &FrameTaskGraph,
+        allocations: &mut FrameAllocations,
+        res: Res,
+        usage_of: &[Res],
+        image: vk::Image,
+    ) -> Result<Resolved> {
+        let name = CString::new(*graph.resource_tag(res)).unwrap();
+        self.debug_utils.name_image(&self.device, image, &name)?;
+
+        let desc = graph.image_desc(res).unwrap();
+        let aspect = format_aspect(desc.format);
+        let view = create_image_view(
+            &self.device,
+            image,
+            view_type(desc, &desc.full_range()),
+            desc.format,
+            desc.full_range().to_vk(aspect),
+        )?;
+        allocations.views.push(view);
+
+        let mut ranges = usage_of
+            .iter()
+            .flat_map(|&res| graph.image_ranges(res))
+            .collect::<Vec<_>>();
+        ranges.sort_unstable();
+        ranges.dedup();
+
+        let mut range_views = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            let range_view = create_image_view(
+                &self.device,
+                image,
+                view_type(desc, &range),
+                desc.format,
+                range.to_vk(aspect),
+            )?;
+            allocations.views.push(range_view);
+            range_views.push((range, range_view));
+        }
+
+        Ok(Resolved::Image(
+            ResolvedImage {
+                image,
+                view,
+                aspect,
+            },
+            range_views,
+        ))
+    }
+
+    unsafe fn name_frame_graph_buffer(
+        &self,
+        graph: &FrameTaskGraph,
+        res: Res,
+        buffer: vk::Buffer,
+    ) -> Result<Resolved> {
+        let name = CString::new(*graph.resource_tag(res)).unwrap();
+        self.debug_utils.name_buffer(&self.device, buffer, &name)?;
+        Ok(Resolved::Buffer(buffer))
+    }
+
+    /// Bring both allocations of every history to the state the first frame expects them in.
+    ///
+    /// Their content is undefined until a frame writes them.
+    unsafe fn initialize_frame_graph_histories(
+        &self,
+        graph: &FrameTaskGraph,
+        compiled: &CompiledGraph,
+        allocations: &FrameAllocations,
+    ) -> Result<()> {
+        if allocations.histories.is_empty() {
+            return Ok(());
+        }
+
+        let queue = self.queues.graphics();
+        let cmd = self.record_one_time_command_buffer(queue.pool, |cmd| {
+            for (history, state) in compiled.history_states() {
+                let (_, slots) = allocations
+                    .histories
+                    .iter()
+                    .find(|(allocated, _)| allocated == history)
+                    .unwrap();
+
+                for slot in slots {
+                    let mut resources = ResolvedResources::default();
+                    slot.bind(&mut resources, history.current);
+
+                    let barrier = Barrier {
+                        resource: history.current,
+                        range: graph.full_range(history.current),
+                        previous_accesses: vec![AccessType::Nothing],
+                        next_accesses: state.accesses.clone(),
+                        previous_layout: ImageLayout::Optimal,
+                        next_layout: state.layout,
+                        discard_contents: true,
+                        queue_transfer: None,
+                    };
+                    self.cmd_frame_graph_barriers(
+                        cmd,
+                        &resources,
+                        vk::DependencyFlags::empty(),
+                        std::iter::once((&barrier, BarrierHalf::Full)),
+                        &[],
+                    );
+                }
+            }
+            Ok(())
+        })?;
+
+        self.submit_to_queue_with_fence(queue.queue, cmd, &[], &[], &[], vk::Fence::null())?;
+        self.device.queue_wait_idle(queue.queue)?;
+        self.device.free_command_buffers(queue.pool, &[cmd]);
+
+        Ok(())
+    }
+
+    /// One semaphore per wait between two submissions, the final submission waits on the
+    /// last compute one so the frame fence covers everything.
+    unsafe fn create_frame_graph_semaphores(
+        &self,
+        compiled: &CompiledGraph,
+        allocations: &mut FrameAllocations,
+    ) -> Result<()> {
+        let submissions = compiled.submissions();
+        let last_compute = submissions
+            .iter()
+            .rposition(|submission| submission.queue == QueueType::Compute);
+
+        let waits = submissions
+            .iter()
+            .enumerate()
+            .flat_map(|(i, submission)| submission.waits.iter().map(move |&from| (from, i)))
+            .chain(last_compute.map(|from| (from, submissions.len())))
+            .collect::<Vec<_>>();
+
+        for (from, to) in waits {
+            let semaphore = self
+                .device
+                .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)?;
+            allocations.links.push((from, to, semaphore));
+        }
+
+        Ok(())
+    }
+
+    /// The release half of every ownership transfer, found on the barrier acquiring it.
+    fn ownership_releases(&self, compiled: &CompiledGraph) -> Vec<Vec<Barrier>> {
+        let mut releases = vec![Vec::new(); compiled.passes().len()];
+
+        let barriers = compiled
+            .passes()
+            .iter()
+            .flat_map(|pass| &pass.barriers)
+            .chain(compiled.final_barriers());
+        for barrier in barriers {
+            if self.barrier_half(barrier) == BarrierHalf::Acquire && !barrier.discard_contents {
+                let transfer = barrier.queue_transfer.unwrap();
+                releases[transfer.release_after].push(barrier.clone());
+            }
+        }
+
+        releases
+    }
+
+    fn barrier_half(&self, barrier: &Barrier) -> BarrierHalf {
+        match barrier.queue_transfer {
+            Some(transfer)
+                if self.queue_family(transfer.from) != self.queue_family(transfer.to) =>
+            {
+                BarrierHalf::Acquire
+            }
+            _ => BarrierHalf::Full,
+        }
+    }
+
+    fn queue_family(&self, queue: QueueType) -> u32 {
+        match queue {
+            QueueType::Graphics => self.queues.indices.graphics,
+            QueueType::Compute => self.queues.indices.compute,
+        }
+    }
+
+    pub(crate) fn lock_queue(&self, queue: QueueType) -> ReentrantMutexGuard<'_, QueueWithPool> {
+        match queue {
+            QueueType::Graphics => self.queues.graphics(),
+            QueueType::Compute => self.queues.compute(),
+        }
+    }
+
+    /// Get the graph ready for the next frame, recompiling it if its structure changed.
+    ///
+    /// The frame fence must have been waited on.
+    pub(crate) unsafe fn prepare_frame_graph(&self, frame: &mut FrameGraph) -> Result<()> {
+        // Nothing uses them anymore
+        frame.free_command_buffers(self);
+        self.compile_frame_graph(frame)?;
+
+        if frame.cache.get_mut().is_none() {
+            return Err(PompeiiError::NoFrameGraph);
+        }
+        Ok(())
+    }
+
+    /// Record every pass of the prepared graph, drawing to the given swapchain image.
+    ///
+    /// The first graphics submission waits for the image to be available, the passes don't
+    /// say when they first touch it. A last graphics submission hands the resources back and
+    /// signals that the frame is finished.
+    pub(crate) unsafe fn record_frame_graph(
+        &self,
+        frame: &mut FrameGraph,
+        backbuffer: ResolvedImage,
+    ) -> Result<Vec<RecordedSubmission>> {
+        let (compiled, allocations) = frame.cache.get_mut().ok_or(PompeiiError::NoFrameGraph)?;
+        allocations
+            .resources
+            .bind_image(frame.backbuffer, backbuffer);
+
+        // What was written last frame is now read as the previous content
+        for (history, slots) in &allocations.histories {
+            slots[frame.frame_index % 2].bind(&mut allocations.resources, history.current);
+            slots[(frame.frame_index + 1) % 2].bind(&mut allocations.resources, history.previous);
+        }
+
+        for &(from, to) in compiled.forwarded_resources() {
+            allocations.resources.bind_forwarded(from, to);
+        }
+
+        let submissions = compiled.submissions();
+        let first_graphics = submissions
+            .iter()
+            .position(|submission| submission.queue == QueueType::Graphics)
+            .unwrap_or(submissions.len());
+
+        let mut recorded = Vec::with_capacity(submissions.len() + 1);
+        for (i, submission) in submissions.iter().enumerate() {
+            let queue = self.lock_queue(submission.queue);
+
+            let cmd = self.record_one_time_command_buffer(queue.pool, |cmd| {
+                for index in submission.passes.clone() {
+                    let pass = &compiled.passes()[index];
+
+                    // The barriers left inside a shared rendering scope are between
+                    // attachments read at the same pixel. Local reads are off, so until they
+                    // can be enabled every pass is in its own scope
+                    let render_scope = compiled.render_scope(index);
+                    let dependency_flags = match render_scope {
+                        RenderScope::Continue | RenderScope::End => vk::DependencyFlags::BY_REGION,
+                        RenderScope::Own | RenderScope::Begin => vk::DependencyFlags::empty(),
+                    };
+
+                    self.cmd_frame_graph_barriers(
+                        cmd,
+                        &allocations.resources,
+                        dependency_flags,
+                        pass.barriers
+                            .iter()
+                            .map(|barrier| (barrier, self.barrier_half(barrier))),
+                        &allocations.aliasing[index],
+                    );
+
+                    frame.graph.record_pass(
+                        pass.action,
+                        &PassContext {
+                            device: &self.device,
+                            command_buffer: cmd,
+                            resources: &allocations.resources,
+                            render_scope,
+                        },
+                    );
+
+                    self.cmd_frame_graph_barriers(
+                        cmd,
+                        &allocations.resources,
+                        vk::DependencyFlags::empty(),
+                        allocations.releases[index]
+                            .iter()
+                            .map(|barrier| (barrier, BarrierHalf::Release)),
+                        &[],
+                    );
+                }
+                Ok(())
+            })?;
+            frame.in_flight.push((submission.queue, cmd));
+
+            let (mut waits, signals) = frame_graph_semaphores(&allocations.links, i);
+            if i == first_graphics {
+                waits.push(self.image_available_semaphore);
+            }
+            recorded.push(RecordedSubmission {
+                queue: submission.queue,
+                cmd,
+                waits,
+                signals,
+            });
+        }
+
+        // Hand the resources over to the presentation engine and the host
+        let queue = self.queues.graphics();
+        let cmd = self.record_one_time_command_buffer(queue.pool, |cmd| {
+            self.cmd_frame_graph_barriers(
+                cmd,
+                &allocations.resources,
+                vk::DependencyFlags::empty(),
+                compiled
+                    .final_barriers()
+                    .iter()
+                    .map(|barrier| (barrier, self.barrier_half(barrier))),
+                &[],
+            );
+            Ok(())
+        })?;
+        frame.in_flight.push((QueueType::Graphics, cmd));
+
+        let (mut waits, _) = frame_graph_semaphores(&allocations.links, submissions.len());
+        if first_graphics == submissions.len() {
+            waits.push(self.image_available_semaphore);
+        }
+        recorded.push(RecordedSubmission {
+            queue: QueueType::Graphics,
+            cmd,
+            waits,
+            signals: vec![self.render_finished_semaphore],
+        });
+
+        Ok(recorded)
+    }
+
+    /// Submit a recorded frame, the last submission signaling the frame fence.
+    pub(crate) unsafe fn submit_frame_graph(
+        &self,
+        frame: &mut FrameGraph,
+        recorded: Vec<RecordedSubmission>,
+    ) -> Result<()> {
+        let last = recorded.len() - 1;
+        let mut acquire_waited = false;
+        for (i, submission) in recorded.into_iter().enumerate() {
+            let result = self.submit_recorded(&submission, i == last);
+            if let Err(err) = result {
+                self.abandon_frame(!acquire_waited);
+                return Err(err);
+            }
+            acquire_waited |= submission.waits.contains(&self.image_available_semaphore);
+        }
+        frame.frame_index += 1;
+
+        Ok(())
+    }
+
+    /// Submit a command buffer of the frame, resetting the frame fence right before the
+    /// submission signaling it.
+    unsafe fn submit_recorded(
+        &self,
+        submission: &RecordedSubmission,
+        signal_fence: bool,
+    ) -> Result<()> {
+        let queue = self.lock_queue(submission.queue);
+        let stages = vec![vk::PipelineStageFlags::ALL_COMMANDS; submission.waits.len()];
+
+        let fence = if signal_fence {
+            self.device.reset_fences(&[self.in_flight_fence])?;
+            self.in_flight_fence
+        } else {
+            vk::Fence::null()
+        };
+        self.submit_to_queue_with_fence(
+            queue.queue,
+            submission.cmd,
+            &submission.waits,
+            &stages,
+            &submission.signals,
+            fence,
+        )
+    }
+
+    /// Leave things as the next frame expects them after this one failed partway: the frame
+    /// fence signaled once what was submitted is done, and the swapchain image semaphore
+    /// unsignaled if nothing waited on it yet.
+    pub(crate) unsafe fn abandon_frame(&self, unsignal_acquire: bool) {
+        let waits = if unsignal_acquire {
+            vec![self.image_available_semaphore]
+        } else {
+            Vec::new()
+        };
+        let stages = vec![vk::PipelineStageFlags::ALL_COMMANDS; waits.len()];
+        let submit_info = vk::SubmitInfo::builder()
+            .wait_semaphores(&waits)
+            .wait_dst_stage_mask(&stages);
+
+        let queue = self.queues.graphics();
+        let result = self
+            .device
+            .reset_fences(&[self.in_flight_fence])
+            .and_then(|()| {
+                self.device.queue_submit(
+                    queue.queue,
+                    from_ref(&submit_info.build()),
+                    self.in_flight_fence,
+                )
+            });
+        if let Err(err) = result {
+            warn!("Could not clean up after a failed frame: {}", err);
+        }
+    }
+
+    /// Record barriers of a compiled graph in a single pipeline barrier, along with the
+    /// aliasing barriers the resources they transition for the first time wait on.
+    unsafe fn cmd_frame_graph_barriers<'a>(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        resources: &ResolvedResources,
+        dependency_flags: vk::DependencyFlags,
+        barriers: impl Iterator<Item = (&'a Barrier, BarrierHalf)>,
+        aliasing: &[AliasingBarrier],
+    ) {
+        let mut src_stages = vk::PipelineStageFlags::empty();
+        let mut dst_stages = vk::PipelineStageFlags::empty();
+        let mut memory_barriers = Vec::new();
+        let mut image_barriers = Vec::new();
+        let mut buffer_barriers = Vec::new();
+
+        for barrier in aliasing {
+            let (src, dst, vk_barrier) = vk_sync_fork::get_memory_barrier(&GlobalBarrier {
+                previous_accesses: &barrier.previous_accesses,
+                next_accesses: &[AccessType::Nothing],
+            });
+            src_stages |= src;
+            dst_stages |= dst;
+            memory_barriers.push(vk_barrier);
+        }
+
+        for (barrier, half) in barriers {
+            let (src_family, dst_family) = match (half, barrier.queue_transfer) {
+                (BarrierHalf::Full, _) | (_, None) => {
+                    (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+                }
+                // Without content there is nothing to transfer, it only has to wait
+                (BarrierHalf::Acquire, Some(_)) if barrier.discard_contents => {
+                    (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+                }
+                (_, Some(transfer)) => (
+                    self.queue_family(transfer.from),
+                    self.queue_family(transfer.to),
+                ),
+            };
+
+            let (src, dst) = if let Some(image) = resources.resolved_image(barrier.resource) {
+                let (src, dst, mut vk_barrier) =
+                    vk_sync_fork::get_image_memory_barrier(&ImageBarrier {
+                        previous_accesses: &barrier.previous_accesses,
+                        next_accesses: &barrier.next_accesses,
+                        previous_layout: barrier.previous_layout,
+                        next_layout: barrier.next_layout,
+                        discard_contents: barrier.discard_contents,
+                        src_queue_family_index: src_family,
+                        dst_queue_family_index: dst_family,
+                        image: image.image,
+                        range: barrier.range.to_vk(image.aspect),
+                    });
+                strip_other_half(
+                    half,
+                    &mut vk_barrier.src_access_mask,
+                    &mut vk_barrier.dst_access_mask,
+                );
+                image_barriers.push(vk_barrier);
+                (src, dst)
+            } else {
+                let (src, dst, mut vk_barrier) =
+                    vk_sync_fork::get_buffer_memory_barrier(&BufferBarrier {
+                        previous_accesses: &barrier.previous_accesses,
+                        next_accesses: &barrier.next_accesses,
+                        src_queue_family_index: src_family,
+                        dst_queue_family_index: dst_family,
+                        buffer: resources.buffer(barrier.resource),
+                        offset: 0,
+                        size: vk::WHOLE_SIZE as _,
+                    });
+                strip_other_half(
+                    half,
+                    &mut vk_barrier.src_access_mask,
+                    &mut vk_barrier.dst_access_mask,
+                );
+                buffer_barriers.push(vk_barrier);
+                (src, dst)
+            };
+
+            // The other half of a transfer happens on another queue which may not support the
+            // same stages
+            match half {
+                BarrierHalf::Full => {
+                    src_stages |= src;
+                    dst_stages |= dst;
+                }
+                BarrierHalf::Release => {
+                    src_stages |= src;
+                    dst_stages |= vk::PipelineStageFlags::BOTTOM_OF_PIPE;
+                }
+                BarrierHalf::Acquire => {
+                    src_stages |= vk::PipelineStageFlags::TOP_OF_PIPE;
+                    dst_stages |= dst;
+                }
+            }
+        }
+
+        if memory_barriers.is_empty() && image_barriers.is_empty() && buffer_barriers.is_empty() {
+            return;
+        }
+
+        self.device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stages,
+            dst_stages,
+            dependency_flags,
+            &memory_barriers,
+            &buffer_barriers,
+            &image_barriers,
+        );
+    }
+}
+
+fn strip_other_half(
+    half: BarrierHalf,
+    src_access: &mut vk::AccessFlags,
+    dst_access: &mut vk::AccessFlags,
+) {
+    match half {
+        BarrierHalf::Full => {}
+        BarrierHalf::Release => *dst_access = vk::AccessFlags::empty(),
+        BarrierHalf::Acquire => *src_access = vk::AccessFlags::empty(),
+    }
+}
+
+/// The semaphores submission `index` waits on and signals.
+fn frame_graph_semaphores(
+    links: &[(usize, usize, vk::Semaphore)],
+    index: usize,
+) -> (Vec<vk::Semaphore>, Vec<vk::Semaphore>) {
+    let waits = links
+        .iter()
+        .filter(|(_, to, _)| *to == index)
+        .map(|(_, _, semaphore)| *semaphore)
+        .collect();
+    let signals = links
+        .iter()
+        .filter(|(from, _, _)| *from == index)
+        .map(|(_, _, semaphore)| *semaphore)
+        .collect();
+
+    (waits, signals)
+}
+
+/// 3D images are always viewed whole, for the others it depends on the number of layers.
+fn view_type(desc: &ImageDesc, range: &SubresourceRange) -> vk::ImageViewType {
+    if desc.extent.depth > 1 {
+        vk::ImageViewType::TYPE_3D
+    } else if range.layer_count > 1 {
+        vk::ImageViewType::TYPE_2D_ARRAY
+    } else {
+        vk::ImageViewType::TYPE_2D
+    }
+}
+
+/// An image described by `desc`, usable the way all of `usage_of` are used.
+fn frame_graph_image_info(
+    graph: &FrameTaskGraph,
+    desc: &ImageDesc,
+    usage_of: &[Res],
+) -> vk::ImageCreateInfo {
+    let usage = usage_of
+        .iter()
+        .fold(vk::ImageUsageFlags::empty(), |usage, &res| {
+            usage | graph.image_usage(res)
+        });
+
+    vk::ImageCreateInfo::builder()
+        .image_type(if desc.extent.depth > 1 {
+            vk::ImageType::TYPE_3D
+        } else {
+            vk::ImageType::TYPE_2D
+        })
+        .format(desc.format)
+        .extent(desc.extent)
+        .mip_levels(desc.mip_levels)
+        .array_layers(desc.array_layers)
+        .samples(desc.samples)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .build()
+}
+
+fn frame_graph_buffer_usage(graph: &FrameTaskGraph, usage_of: &[Res]) -> vk::BufferUsageFlags {
+    usage_of
+        .iter()
+        .fold(vk::BufferUsageFlags::empty(), |usage, &res| {
+            usage | graph.buffer_usage(res)
+        })
+}
+
+fn frame_graph_buffer_info(
+    graph: &FrameTaskGraph,
+    res: Res,
+    usage_of: &[Res],
+) -> vk::BufferCreateInfo {
+    vk::BufferCreateInfo::builder()
+        .size(graph.buffer_size(res).unwrap())
+        .usage(frame_graph_buffer_usage(graph, usage_of))
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .build()
+}
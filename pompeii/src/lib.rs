@@ -4,13 +4,18 @@ pub use ash;
 use ash::vk;
 use log::debug;
 use parking_lot::{Mutex, RwLock};
+pub use pompeii_task;
 pub use vk_mem;
 
 use debug_utils::DebugUtils;
 use setup::*;
 
 use crate::{
-    alloc::VmaPools,
+    alloc::{TransferWaiter, VmaPools},
+    deletion::DeletionQueue,
+    frame_graph::{FrameGraph, FrameGraphBuilder},
+    memory::MemoryCounters,
+    staging::StagingRing,
     swapchain::{SurfaceWrapper, SwapchainWrapper},
 };
 
@@ -18,11 +23,15 @@ pub mod acceleration_structure;
 pub mod alloc;
 mod commands;
 mod debug_utils;
+mod deletion;
 pub mod descriptor_sets;
+pub mod frame_graph;
 mod images;
+pub mod memory;
 pub mod mesh;
 mod render;
 pub mod setup;
+pub mod staging;
 mod swapchain;
 mod sync;
 pub(crate) mod utils;
@@ -64,6 +73,34 @@ pub mod errors {
         NoVertexUv,
         #[error("Not an indexed model")]
         NoModelIndices,
+        #[error("{0}")]
+        TaskGraph(#[from] pompeii_task::errors::TaskGraphError),
+        #[error("No frame graph to render")]
+        NoFrameGraph,
+        #[error("Format {0:?} doesn't support linear filtered blits")]
+        NoLinearBlit(ash::vk::Format),
+        #[error("Staging ring full, couldn't stage {0} bytes")]
+        StagingRingFull(u64),
+        #[error("Can't stage {size} bytes in a staging ring of {capacity} bytes")]
+        StagingTooLarge { size: u64, capacity: u64 },
+        #[error("Nothing to stage")]
+        EmptyStaging,
+        #[error("A texture needs at least one mip level")]
+        NoMipLevels,
+        #[error("Format {0:?} has no fixed texel size")]
+        UnsupportedTextureFormat(ash::vk::Format),
+        #[error("Texture data is {actual} bytes, expected {expected}")]
+        TextureDataSize { expected: u64, actual: u64 },
+        #[error("Reading {size} bytes from {offset} overflows a buffer of {buffer_size} bytes")]
+        ReadOutOfBounds {
+            offset: u64,
+            size: u64,
+            buffer_size: u64,
+        },
+        #[error("Texels of format {format:?} can't be read as elements of {size} bytes")]
+        ReadbackElementSize { format: ash::vk::Format, size: u64 },
+        #[error("Readback from another transfer ticket")]
+        ForeignReadback,
     }
 }
 
@@ -84,19 +121,21 @@ pub struct PompeiiRenderer {
     // Deletion queue for main objects that are freed when the renderer is dropped
     pub(crate) main_deletion_queue:
         Mutex<Vec<Box<dyn FnOnce(&PompeiiRenderer) -> errors::Result<()> + Send + Sync>>>,
-    // Deletion queue for the allocations that need freeing
-    pub(crate) alloc_deletion_queue: Mutex<
-        Vec<
-            Box<
-                dyn FnOnce(
-                        (&ash::Device, &ash::extensions::khr::AccelerationStructure),
-                        &Arc<vk_mem::Allocator>,
-                    ) -> errors::Result<()>
-                    + Send
-                    + Sync,
-            >,
-        >,
-    >,
+    // Objects dropped while the GPU may still use them, freed as frames and transfers complete
+    pub(crate) deletion_queue: Arc<DeletionQueue>,
+    // Wakes the tasks awaiting transfers
+    pub(crate) transfer_waiter: TransferWaiter,
+    // Live allocations made by the helpers, by category
+    pub(crate) memory_counters: MemoryCounters,
+    // Whether VK_EXT_memory_budget is enabled
+    pub(crate) memory_budget: bool,
+
+    // Uploads for the next frame
+    pub(crate) staging_ring: Mutex<StagingRing>,
+
+    // What gets rendered every frame, rebuilt when the swapchain changes
+    pub(crate) frame_graph_builder: Mutex<FrameGraphBuilder>,
+    pub(crate) frame_graph: Mutex<Option<FrameGraph>>,
 
     pub(crate) image_available_semaphore: vk::Semaphore,
     pub(crate) render_finished_semaphore: vk::Semaphore,
@@ -105,18 +144,24 @@ pub struct PompeiiRenderer {
 
 impl Drop for PompeiiRenderer {
     fn drop(&mut self) {
+        self.transfer_waiter.stop();
+
         unsafe {
             // Wait for frame to finish
             self.device
                 .wait_for_fences(&[self.in_flight_fence], true, u64::MAX)
                 .unwrap();
 
-            // Free everything
-            let mut alloc_deletion_queue = self.alloc_deletion_queue.lock();
-            for free in alloc_deletion_queue.drain(..).rev() {
-                free((&self.device, &self.ext_acceleration_structure), &self.vma).unwrap();
+            if let Some(frame_graph) = self.frame_graph.get_mut().take() {
+                frame_graph.destroy(self);
             }
 
+            self.staging_ring.lock().destroy(self);
+
+            // Free everything, transfers may still be running
+            self.device.device_wait_idle().unwrap();
+            self.destroy_pending();
+
             debug!("Freed everything");
 
             // VMA
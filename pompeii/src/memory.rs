@@ -0,0 +1,139 @@
+//! GPU memory usage, to spot leaks and report to diagnostics
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ash::vk;
+
+use crate::{alloc::VkBufferHandle, errors::Result, PompeiiRenderer};
+
+/// What an allocation is for, after the helper that made it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    Vertex,
+    Index,
+    AccelerationStructure,
+    /// Allocated from the acceleration structure scratch pool
+    Scratch,
+    Staging,
+    Readback,
+    Texture,
+}
+
+impl MemoryCategory {
+    pub const ALL: [MemoryCategory; 7] = [
+        MemoryCategory::Vertex,
+        MemoryCategory::Index,
+        MemoryCategory::AccelerationStructure,
+        MemoryCategory::Scratch,
+        MemoryCategory::Staging,
+        MemoryCategory::Readback,
+        MemoryCategory::Texture,
+    ];
+}
+
+/// Live allocations of every category.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryCounters([AtomicU64; MemoryCategory::ALL.len()]);
+
+impl MemoryCounters {
+    pub(crate) fn track(&self, category: MemoryCategory) {
+        self.0[category as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn untrack(&self, category: MemoryCategory) {
+        self.0[category as usize].fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn count(&self, category: MemoryCategory) -> u64 {
+        self.0[category as usize].load(Ordering::Relaxed)
+    }
+}
+
+/// Usage of a memory heap, as seen by VMA and the driver.
+#[derive(Debug, Copy, Clone)]
+pub struct HeapReport {
+    pub flags: vk::MemoryHeapFlags,
+    pub size: vk::DeviceSize,
+    /// Only known when the device supports `VK_EXT_memory_budget`
+    pub budget: Option<HeapBudget>,
+    pub block_count: u32,
+    pub block_bytes: vk::DeviceSize,
+    pub allocation_count: u32,
+    pub allocation_bytes: vk::DeviceSize,
+    pub largest_free_block: vk::DeviceSize,
+}
+
+/// What the driver reports about a heap.
+#[derive(Debug, Copy, Clone)]
+pub struct HeapBudget {
+    /// Used by the whole process, including other allocators
+    pub usage: vk::DeviceSize,
+    /// How much the process can use before allocations start failing or hurting performance
+    pub budget: vk::DeviceSize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryReport {
+    pub heaps: Vec<HeapReport>,
+    /// Live allocations of every [category](MemoryCategory::ALL), in that order
+    pub allocations: Vec<(MemoryCategory, u64)>,
+    /// The largest free range in the memory blocks VMA allocated, across every heap
+    pub largest_free_block: vk::DeviceSize,
+}
+
+impl PompeiiRenderer {
+    /// Statistics of every allocation made through VMA, along with the budget of every heap
+    /// when `VK_EXT_memory_budget` is supported.
+    pub fn memory_report(&self) -> Result<MemoryReport> {
+        let mut budgets = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut properties = vk::PhysicalDeviceMemoryProperties2::builder();
+        if self.memory_budget {
+            properties = properties.push_next(&mut budgets);
+        }
+        unsafe {
+            self.instance
+                .get_physical_device_memory_properties2(self.physical_device, &mut properties)
+        };
+        let properties = properties.memory_properties;
+        let stats = self.vma.calculate_stats()?;
+
+        let heaps = properties.memory_heaps[..properties.memory_heap_count as usize]
+            .iter()
+            .zip(&stats.memoryHeap)
+            .enumerate()
+            .map(|(i, (heap, stats))| HeapReport {
+                flags: heap.flags,
+                size: heap.size,
+                budget: self.memory_budget.then(|| HeapBudget {
+                    usage: budgets.heap_usage[i],
+                    budget: budgets.heap_budget[i],
+                }),
+                block_count: stats.blockCount,
+                block_bytes: stats.usedBytes + stats.unusedBytes,
+                allocation_count: stats.allocationCount,
+                allocation_bytes: stats.usedBytes,
+                largest_free_block: stats.unusedRangeSizeMax,
+            })
+            .collect();
+
+        Ok(MemoryReport {
+            heaps,
+            allocations: MemoryCategory::ALL
+                .iter()
+                .map(|&category| (category, self.memory_counters.count(category)))
+                .collect(),
+            largest_free_block: stats.total.unusedRangeSizeMax,
+        })
+    }
+
+    /// Count the buffer in its category until it is freed with
+    /// [free_buffer](PompeiiRenderer::free_buffer).
+    pub(crate) fn track_buffer(
+        &self,
+        mut buffer: VkBufferHandle,
+        category: MemoryCategory,
+    ) -> VkBufferHandle {
+        self.memory_counters.track(category);
+        buffer.category = Some(category);
+        buffer
+    }
+}
@@ -1,6 +1,6 @@
 use ash::vk;
 
-use crate::{alloc::VkBufferHandle, PompeiiRenderer};
+use crate::{alloc::Buffer, PompeiiRenderer};
 
 pub trait MeshVertex {
     fn format() -> vk::Format;
@@ -35,28 +35,13 @@ impl MeshIndex for u16 {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Mesh {
-    pub(crate) vertex_buffer: VkBufferHandle,
-    pub(crate) index_buffer: VkBufferHandle,
+    pub(crate) vertex_buffer: Buffer,
+    pub(crate) index_buffer: Buffer,
     pub(crate) sub_meshes: Box<[SubMesh]>,
 }
 
-impl Mesh {
-    pub fn destroy_on_exit(&self, renderer: &PompeiiRenderer) {
-        let vert = self.vertex_buffer.clone();
-        let index = self.index_buffer.clone();
-        renderer
-            .alloc_deletion_queue
-            .lock()
-            .push(Box::new(move |_, vma| unsafe {
-                vert.destroy(vma);
-                index.destroy(vma);
-                Ok(())
-            }));
-    }
-}
-
 impl Into<SubMesh> for (usize, usize, usize, usize) {
     fn into(self) -> SubMesh {
         SubMesh {
@@ -85,8 +70,8 @@ impl SubMesh {
 impl PompeiiRenderer {
     pub fn create_mesh(
         &self,
-        vertices: VkBufferHandle,
-        indices: VkBufferHandle,
+        vertices: Buffer,
+        indices: Buffer,
         sub_meshes: impl Iterator<Item = impl Into<SubMesh>>,
     ) -> Mesh {
         Mesh {
@@ -2,39 +2,34 @@ use std::array::from_ref;
 
 use ash::vk;
 use log::{trace, warn};
-use vk_sync_fork::{AccessType, ImageLayout};
-
-use crate::{errors::Result, PompeiiRenderer};
-
-impl PompeiiRenderer {
-    unsafe fn record_render_commands(
-        &self,
-        pool: vk::CommandPool,
-        swapchain_image_index: u32,
-    ) -> Result<vk::CommandBuffer> {
-        let swapchain = self.swapchain.read();
-
-        self.record_one_time_command_buffer(pool, |cmd| {
-            self.cmd_sync_image_barrier(
-                cmd,
-                &[AccessType::Present],
-                &[AccessType::ColorAttachmentWrite],
-                ImageLayout::Optimal,
-                ImageLayout::Optimal,
-                true,
-                swapchain.images[swapchain_image_index as usize],
-                vk::ImageAspectFlags::COLOR,
-            );
-
-            self.device.cmd_begin_rendering(
-                cmd,
+use pompeii_task::{Res, ResolvedImage};
+
+use crate::{
+    errors::{PompeiiError, Result},
+    frame_graph::FrameTaskGraph,
+    PompeiiRenderer,
+};
+
+/// What gets rendered until the user sets a frame graph: the backbuffer cleared to grey.
+pub(crate) fn clear_backbuffer(graph: &mut FrameTaskGraph, backbuffer: Res) {
+    let extent = graph.image_desc(backbuffer).unwrap().extent;
+
+    graph
+        .create_raster_pass("Clear")
+        .add_color_attachment(backbuffer)
+        .record(move |ctx| unsafe {
+            ctx.device.cmd_begin_rendering(
+                ctx.command_buffer,
                 &vk::RenderingInfoKHR::builder()
-                    .render_area(vk::Rect2D::from(swapchain.extent))
+                    .render_area(vk::Rect2D::from(vk::Extent2D {
+                        width: extent.width,
+                        height: extent.height,
+                    }))
                     .layer_count(1)
                     .view_mask(0)
                     .color_attachments(from_ref(
                         &vk::RenderingAttachmentInfoKHR::builder()
-                            .image_view(swapchain.image_views[swapchain_image_index as usize])
+                            .image_view(ctx.resources.image_view(backbuffer))
                             .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
                             .load_op(vk::AttachmentLoadOp::CLEAR)
                             .store_op(vk::AttachmentStoreOp::STORE)
@@ -47,32 +42,28 @@ impl PompeiiRenderer {
                     )),
             );
 
-            self.device.cmd_end_rendering(cmd);
-
-            self.cmd_sync_image_barrier(
-                cmd,
-                &[AccessType::ColorAttachmentWrite],
-                &[AccessType::Present],
-                ImageLayout::Optimal,
-                ImageLayout::Optimal,
-                false,
-                swapchain.images[swapchain_image_index as usize],
-                vk::ImageAspectFlags::COLOR,
-            );
-
-            Ok(())
-        })
-    }
+            ctx.device.cmd_end_rendering(ctx.command_buffer);
+        });
+}
 
+impl PompeiiRenderer {
     /// Return whether or not to recreate the swapchain before next round
     pub fn render_and_present(&self) -> Result<bool> {
-        // Wait for previous frame
+        // Wait for previous frame, the fence is only reset right before the submission signaling
+        // it again
         unsafe {
             self.device
                 .wait_for_fences(&[self.in_flight_fence], true, u64::MAX)?;
-            self.device.reset_fences(&[self.in_flight_fence])?;
+
+            // Before recording, what gets dropped from now on may still be used by this frame
+            self.staging_ring.lock().reclaim_frame();
+            self.destroy_completed();
         }
 
+        let mut frame_graph = self.frame_graph.lock();
+        let frame_graph = frame_graph.as_mut().ok_or(PompeiiError::NoFrameGraph)?;
+        unsafe { self.prepare_frame_graph(frame_graph)? };
+
         trace!("[Render] Start commands");
 
         let swapchain = self.swapchain.read();
@@ -86,36 +77,37 @@ impl PompeiiRenderer {
             )?
         };
 
-        let graphics_queue = self.queues.graphics();
-        let render_commands =
-            unsafe { self.record_render_commands(graphics_queue.pool, swapchain_image_index)? };
-
-        let wait_semaphores = [self.image_available_semaphore];
-        let signal_semaphores = [self.render_finished_semaphore];
-
         unsafe {
-            self.submit_to_queue_with_fence(
-                graphics_queue.queue,
-                render_commands,
-                &[self.image_available_semaphore],
-                &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
-                &[self.render_finished_semaphore],
-                self.in_flight_fence,
-            )?;
+            let recorded = self.record_frame_graph(
+                frame_graph,
+                ResolvedImage {
+                    image: swapchain.images[swapchain_image_index as usize],
+                    view: swapchain.image_views[swapchain_image_index as usize],
+                    aspect: vk::ImageAspectFlags::COLOR,
+                },
+            );
+            let recorded = match recorded {
+                Ok(recorded) => recorded,
+                Err(err) => {
+                    self.abandon_frame(true);
+                    return Err(err);
+                }
+            };
+
+            self.submit_frame_graph(frame_graph, recorded)?;
         }
+        self.staging_ring.lock().end_frame();
+        self.deletion_queue.end_frame();
 
         trace!("[Render] Submitted graphics work");
 
-        // Release the lock on the graphics queue
-        drop(graphics_queue);
-
         unsafe {
             let present_queue = self.queues.present();
             let swapchains = [swapchain.handle];
             let res = swapchain.ext.queue_present(
                 present_queue.queue,
                 &vk::PresentInfoKHR::builder()
-                    .wait_semaphores(&signal_semaphores)
+                    .wait_semaphores(from_ref(&self.render_finished_semaphore))
                     .swapchains(&swapchains)
                     .image_indices(&[swapchain_image_index]),
             );
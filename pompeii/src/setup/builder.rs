@@ -1,17 +1,21 @@
-use std::{io::Write, os::raw::c_char, sync::Arc};
+use std::{ffi::CString, io::Write, os::raw::c_char, sync::Arc};
 
 use ash::vk;
 use parking_lot::{lock_api::Mutex, RwLock};
 
 use crate::{
+    alloc::TransferWaiter,
     debug_utils::DebugUtils,
     errors::{PompeiiError, Result},
+    memory::MemoryCounters,
+    render::clear_backbuffer,
     setup::{
         extensions::get_required_features,
         initializer::PompeiiInitializer,
         physical_device::PhysicalDeviceInfo,
         queues_finder::{DeviceQueues, PhysicalDeviceQueueIndices},
     },
+    staging::{StagingRing, STAGING_RING_SIZE},
     swapchain::{SurfaceWrapper, SwapchainWrapper},
     PompeiiRenderer, VmaPools, VULKAN_VERSION,
 };
@@ -85,6 +89,15 @@ impl PompeiiBuilder {
             .as_ref()
             .ok_or(PompeiiError::NoPhysicalDevicePicked)?;
 
+        // Only used for the budgets of the memory report
+        let memory_budget = physical_device
+            .0
+            .supports_extension(vk::ExtMemoryBudgetFn::name());
+        let mut device_extensions = self.device_extensions.clone();
+        if memory_budget {
+            device_extensions.push(vk::ExtMemoryBudgetFn::name().as_ptr());
+        }
+
         let device = {
             let physical = self
                 .physical_device
@@ -101,7 +114,7 @@ impl PompeiiBuilder {
                         .push_next(&mut f2)
                         .push_next(&mut f3)
                         .push_next(&mut f4)
-                        .enabled_extension_names(&self.device_extensions)
+                        .enabled_extension_names(&device_extensions)
                         .queue_create_infos(&queue_create_info),
                     None,
                 )?
@@ -113,6 +126,11 @@ impl PompeiiBuilder {
             Ok(())
         }));
 
+        let mut vma_flags = vk_mem::AllocatorCreateFlags::KHR_DEDICATED_ALLOCATION
+            | vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS;
+        if memory_budget {
+            vma_flags |= vk_mem::AllocatorCreateFlags::EXT_MEMORY_BUDGET;
+        }
         let vma = Arc::new(vk_mem::Allocator::new(
             vk_mem::AllocatorCreateInfo::new(
                 &self.instance,
@@ -120,13 +138,7 @@ impl PompeiiBuilder {
                 &self.physical_device.as_ref().unwrap().0.handle,
             )
             // TODO: when fixed in master
-            .flags(unsafe {
-                vk_mem::AllocationCreateFlags::from_bits_unchecked(
-                    (vk_mem::AllocatorCreateFlags::KHR_DEDICATED_ALLOCATION
-                        | vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS)
-                        .bits(),
-                )
-            })
+            .flags(unsafe { vk_mem::AllocationCreateFlags::from_bits_unchecked(vma_flags.bits()) })
             .vulkan_api_version(VULKAN_VERSION),
         )?);
 
@@ -220,7 +232,17 @@ impl PompeiiBuilder {
             Ok(())
         }));
 
-        Ok(PompeiiRenderer {
+        let memory_counters = MemoryCounters::default();
+        let staging_ring = unsafe { StagingRing::new(&vma, &memory_counters, STAGING_RING_SIZE)? };
+        self.debug_utils.name_buffer(
+            &device,
+            staging_ring.handle(),
+            &CString::new(format!("Staging Ring (size: {})", STAGING_RING_SIZE)).unwrap(),
+        )?;
+
+        let transfer_waiter = TransferWaiter::new(device.clone());
+
+        let renderer = PompeiiRenderer {
             _entry: self.entry,
             instance: self.instance,
             debug_utils: self.debug_utils,
@@ -237,11 +259,23 @@ impl PompeiiBuilder {
             ext_ray_tracing_pipeline,
 
             main_deletion_queue: Mutex::new(main_deletion_queue),
-            alloc_deletion_queue: Default::default(),
+            deletion_queue: Default::default(),
+            transfer_waiter,
+            memory_counters,
+            memory_budget,
+
+            staging_ring: Mutex::new(staging_ring),
+
+            frame_graph_builder: Mutex::new(Box::new(clear_backbuffer)),
+            frame_graph: Mutex::new(None),
 
             image_available_semaphore,
             render_finished_semaphore,
             in_flight_fence,
-        })
+        };
+
+        renderer.rebuild_frame_graph()?;
+
+        Ok(renderer)
     }
 }
@@ -25,13 +25,16 @@ pub(crate) static REQUIRED_DEVICE_EXTENSIONS: Lazy<[&CStr; 4]> = Lazy::new(|| {
     ]
 });
 
-pub(crate) static REQUIRED_FEATURES_CHECK: [(&str, fn(&PhysicalDeviceInfo) -> bool); 6] = [
+pub(crate) static REQUIRED_FEATURES_CHECK: [(&str, fn(&PhysicalDeviceInfo) -> bool); 7] = [
     ("Descriptor Indexing", |info| {
         info.features_vk12.descriptor_indexing != 0
     }),
     ("Buffer Device Address", |info| {
         info.features_vk12.buffer_device_address != 0
     }),
+    ("Timeline Semaphore", |info| {
+        info.features_vk12.timeline_semaphore != 0
+    }),
     ("Synchronization 2", |info| {
         info.features_vk13.synchronization2 != 0
     }),
@@ -56,6 +59,7 @@ pub(crate) fn get_required_features() -> (
         vk::PhysicalDeviceVulkan12Features::builder()
             .descriptor_indexing(true)
             .buffer_device_address(true)
+            .timeline_semaphore(true)
             .build(),
         vk::PhysicalDeviceVulkan13Features::builder()
             .synchronization2(true)
@@ -61,6 +61,12 @@ impl PhysicalDeviceInfo {
             .map(|heap| heap.size)
             .sum()
     }
+
+    pub fn supports_extension(&self, name: &CStr) -> bool {
+        self.extensions
+            .iter()
+            .any(|ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) } == name)
+    }
 }
 
 impl PompeiiBuilder {
@@ -135,6 +135,7 @@ pub(crate) struct QueueWithPool {
 pub(crate) struct DeviceQueues {
     // Holds unique queues, with a maximum of 4 which only happens if we don't share any queue
     pub(crate) queues: [Option<ReentrantMutex<QueueWithPool>>; 4],
+    pub(crate) indices: PhysicalDeviceQueueIndices,
     pub(crate) graphics_index: usize,
     pub(crate) present_index: usize,
     pub(crate) compute_index: usize,
@@ -194,6 +195,7 @@ impl DeviceQueues {
 
             Ok(Self {
                 queues,
+                indices: indices.clone(),
                 graphics_index: graphics,
                 present_index: present,
                 compute_index: compute,
@@ -234,6 +236,11 @@ impl DeviceQueues {
         self.queues[self.transfer_index].as_ref().unwrap().lock()
     }
 
+    /// One of the unique queues, by its index in `queues`.
+    pub(crate) fn by_index(&self, index: usize) -> ReentrantMutexGuard<QueueWithPool> {
+        self.queues[index].as_ref().unwrap().lock()
+    }
+
     pub(crate) unsafe fn destroy_pools(&self, device: &ash::Device) {
         for queue in self.queues.iter().flatten() {
             device.destroy_command_pool(queue.lock().pool, None);
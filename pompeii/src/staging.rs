@@ -0,0 +1,171 @@
+//! Persistently mapped ring buffer for the data uploaded every frame
+use std::collections::VecDeque;
+
+use ash::vk;
+
+use crate::{
+    alloc::VkBufferHandle,
+    errors::{PompeiiError, Result},
+    memory::{MemoryCategory, MemoryCounters},
+    PompeiiRenderer,
+};
+
+pub(crate) const STAGING_RING_SIZE: vk::DeviceSize = 16 * 1024 * 1024;
+
+/// Data staged in the ring, valid until the GPU is done with the frame it was staged for.
+#[derive(Debug, Copy, Clone)]
+pub struct StagingRegion {
+    pub buffer: vk::Buffer,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+}
+
+impl StagingRegion {
+    /// A copy of the whole region to `dst_offset` in another buffer.
+    pub fn copy_to(&self, dst_offset: vk::DeviceSize) -> vk::BufferCopy {
+        vk::BufferCopy {
+            src_offset: self.offset,
+            dst_offset,
+            size: self.size,
+        }
+    }
+}
+
+pub(crate) struct StagingRing {
+    buffer: VkBufferHandle,
+    mapped: *mut u8,
+    capacity: vk::DeviceSize,
+    // Bytes ever staged and reclaimed, positions in the buffer are these modulo the capacity
+    head: vk::DeviceSize,
+    tail: vk::DeviceSize,
+    // Head at the end of each frame still running on the GPU
+    frames: VecDeque<vk::DeviceSize>,
+}
+
+unsafe impl Send for StagingRing {}
+unsafe impl Sync for StagingRing {}
+
+impl StagingRing {
+    /// Allocate a host coherent ring of `capacity` bytes, a power of two, mapped for its whole
+    /// lifetime so staging never calls into VMA. It is counted as staging memory.
+    pub(crate) unsafe fn new(
+        vma: &vk_mem::Allocator,
+        counters: &MemoryCounters,
+        capacity: vk::DeviceSize,
+    ) -> Result<Self> {
+        debug_assert!(capacity.is_power_of_two());
+
+        let (handle, allocation, info) = vma.create_buffer(
+            &vk::BufferCreateInfo::builder()
+                .size(capacity)
+                .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE),
+            &vk_mem::AllocationCreateInfo::new()
+                .usage(vk_mem::MemoryUsage::CpuToGpu)
+                .required_flags(
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                )
+                .flags(vk_mem::AllocationCreateFlags::MAPPED),
+        )?;
+        let buffer = VkBufferHandle {
+            handle,
+            allocation,
+            info,
+            size: capacity,
+            category: Some(MemoryCategory::Staging),
+        };
+        counters.track(MemoryCategory::Staging);
+
+        Ok(Self {
+            mapped: buffer.info.get_mapped_data(),
+            buffer,
+            capacity,
+            head: 0,
+            tail: 0,
+            frames: VecDeque::new(),
+        })
+    }
+
+    pub(crate) fn handle(&self) -> vk::Buffer {
+        self.buffer.handle
+    }
+
+    /// Copy `data` at the next free offset aligned to `alignment`, a power of two, wrapping
+    /// around instead of splitting it. Fails if `data` is empty or larger than the ring, or if
+    /// the GPU may still read the space it needs.
+    pub(crate) fn push<T: Copy>(
+        &mut self,
+        data: &[T],
+        alignment: vk::DeviceSize,
+    ) -> Result<StagingRegion> {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        if size == 0 {
+            return Err(PompeiiError::EmptyStaging);
+        }
+        if size > self.capacity {
+            return Err(PompeiiError::StagingTooLarge {
+                size,
+                capacity: self.capacity,
+            });
+        }
+
+        let alignment = alignment.max(std::mem::align_of::<T>() as _);
+        debug_assert!(alignment.is_power_of_two() && alignment <= self.capacity);
+
+        let mut start = (self.head + alignment - 1) & !(alignment - 1);
+        if start % self.capacity + size > self.capacity {
+            start = (start / self.capacity + 1) * self.capacity;
+        }
+        if start + size - self.tail > self.capacity {
+            return Err(PompeiiError::StagingRingFull(size));
+        }
+
+        let offset = start % self.capacity;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr() as *const u8,
+                self.mapped.add(offset as usize),
+                size as usize,
+            );
+        }
+        self.head = start + size;
+
+        Ok(StagingRegion {
+            buffer: self.buffer.handle,
+            offset,
+            size,
+        })
+    }
+
+    /// Everything staged so far is used by the frame just submitted.
+    pub(crate) fn end_frame(&mut self) {
+        self.frames.push_back(self.head);
+    }
+
+    /// The oldest frame still running is done, its regions can be reused.
+    pub(crate) fn reclaim_frame(&mut self) {
+        if let Some(end) = self.frames.pop_front() {
+            self.tail = end;
+        }
+    }
+
+    pub(crate) unsafe fn destroy(&self, renderer: &PompeiiRenderer) {
+        renderer.free_buffer(self.buffer.clone());
+    }
+}
+
+impl PompeiiRenderer {
+    /// Copy `data` to the staging ring for the next frame, at an offset aligned to `alignment`.
+    ///
+    /// The region is reused once the frame rendered after this call is done. Fails when `data`
+    /// is empty or larger than the ring, and when the GPU may still read everything there is
+    /// left.
+    pub fn stage<T: Copy>(&self, data: &[T], alignment: vk::DeviceSize) -> Result<StagingRegion> {
+        self.staging_ring.lock().push(data, alignment)
+    }
+
+    /// The buffer regions are staged in, which has `TRANSFER_SRC` usage.
+    pub fn staging_buffer(&self) -> vk::Buffer {
+        self.staging_ring.lock().handle()
+    }
+}
@@ -213,8 +213,10 @@ impl PompeiiRenderer {
         drop(present);
         drop(compute);
         drop(transfer);
+        drop(swapchain);
 
-        Ok(())
+        // Sized after the swapchain
+        self.rebuild_frame_graph()
     }
 }
 
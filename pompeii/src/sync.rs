@@ -13,7 +13,7 @@ impl PompeiiRenderer {
         next_layout: ImageLayout,
         discard_contents: bool,
         image: vk::Image,
-        aspect: vk::ImageAspectFlags,
+        range: vk::ImageSubresourceRange,
     ) {
         let (src_stages, dst_stages, barrier) =
             vk_sync_fork::get_image_memory_barrier(&ImageBarrier {
@@ -25,13 +25,7 @@ impl PompeiiRenderer {
                 src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
                 dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
                 image,
-                range: vk::ImageSubresourceRange::builder()
-                    .aspect_mask(aspect)
-                    .base_mip_level(0)
-                    .level_count(1)
-                    .base_array_layer(0)
-                    .layer_count(1)
-                    .build(),
+                range,
             });
 
         self.device.cmd_pipeline_barrier(